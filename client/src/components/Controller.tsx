@@ -1,40 +1,25 @@
 import { useEffect, useRef } from 'react';
 
-import { Input } from '@/bindings';
 import { useGameStore } from '@/store';
-import { encode } from '@msgpack/msgpack';
 
 const VELOCITY_SCALE = 40;
 const INPUT_SEND_INTERVAL = 100; // Send input every 100ms
 
 export const Controller = () => {
     const ws = useGameStore((s) => s.ws);
-    const clientId = useGameStore((s) => s.clientId);
+    const sendInput = useGameStore((s) => s.sendInput);
     // Track currently-pressed movement keys
     const pressed = useRef<Set<string>>(new Set());
 
     // Helper to transmit velocity to server
     const sendVelocity = (dx: number, dy: number) => {
-        if (!ws || ws.readyState !== WebSocket.OPEN) return;
-        const msg: Input = {
-            playerMove: {
-                playerId: clientId,
-                velocity: { dx, dy }
-            }
-        };
-        ws.send(encode(msg, { useBigInt64: true }));
+        sendInput({ move: { velocity: { dx, dy } } });
     };
 
     // Helper to transmit melee attack to server
     const sendMeleeAttack = () => {
-        if (!ws || ws.readyState !== WebSocket.OPEN) return;
         console.log('sendMeleeAttack');
-        const msg: Input = {
-            playerMelee: {
-                player_id: clientId
-            }
-        };
-        ws.send(encode(msg, { useBigInt64: true }));
+        sendInput('melee');
     };
 
     // Compute current velocity based on pressed keys
@@ -122,7 +107,7 @@ export const Controller = () => {
             window.removeEventListener('blur', handleBlur);
             document.removeEventListener('visibilitychange', handleVisibilityChange);
         };
-    }, [ws, clientId]);
+    }, [ws]);
 
     return null;
 };
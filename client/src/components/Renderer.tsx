@@ -1,6 +1,6 @@
 import { useCallback, useEffect, useState } from 'react';
 
-import { Flag, Player, Snapshot, Team } from '@/bindings';
+import { Flag, Obstacle, Player, Snapshot, Team } from '@/bindings';
 import { useGameStore } from '@/store';
 import { Application, extend } from '@pixi/react';
 
@@ -12,9 +12,9 @@ extend({
     Text
 });
 
-// World dimensions (must stay in sync with Rust constants in game/src/lib.rs)
-const WORLD_WIDTH = 200;
-const WORLD_HEIGHT = 100;
+// World dimensions used until the server sends the map
+const DEFAULT_WORLD_WIDTH = 200;
+const DEFAULT_WORLD_HEIGHT = 100;
 
 // Utility helpers -----------------------------------------------------------
 const teamColor = (team: Team) => (team === 'red' ? 0xff4d4d : 0x4d6dff);
@@ -54,26 +54,42 @@ const createArenaDraw = (width: number, height: number) =>
         g.rect(0, 0, width, height).stroke({ width: 1, color: 'blue', alpha: 0.4 });
     };
 
+const createObstaclesDraw = (obstacles: Obstacle[]) =>
+    function draw(g: Graphics) {
+        g.clear();
+        for (const obstacle of obstacles) {
+            if ('rect' in obstacle) {
+                const { min, max } = obstacle.rect;
+                g.rect(min.x, min.y, max.x - min.x, max.y - min.y).fill(0x555555);
+            } else {
+                const { center, radius } = obstacle.circle;
+                g.circle(center.x, center.y, radius).fill(0x555555);
+            }
+        }
+    };
+
 // Main component ------------------------------------------------------------
 export const Renderer = () => {
     const snapshot: Snapshot | null = useGameStore((s) => s.snapshot);
+    const map = useGameStore((s) => s.map);
     const { width, height } = useViewport();
 
+    const worldWidth = map?.width ?? DEFAULT_WORLD_WIDTH;
+    const worldHeight = map?.height ?? DEFAULT_WORLD_HEIGHT;
+
     // Determine uniform scaling so the world fits in the viewport while preserving aspect ratio.
-    const scale = Math.min(width / WORLD_WIDTH, height / WORLD_HEIGHT);
+    const scale = Math.min(width / worldWidth, height / worldHeight);
 
     // Center the arena within the viewport.
-    const offsetX = (width - WORLD_WIDTH * scale) / 2;
-    const offsetY = (height - WORLD_HEIGHT * scale) / 2;
+    const offsetX = (width - worldWidth * scale) / 2;
+    const offsetY = (height - worldHeight * scale) / 2;
 
     // Pre-compute draw callbacks that depend on scale so they get memoised between renders.
-    const arenaDraw = useCallback(createArenaDraw(WORLD_WIDTH, WORLD_HEIGHT), []);
+    const arenaDraw = useCallback(createArenaDraw(worldWidth, worldHeight), [worldWidth, worldHeight]);
+    const obstaclesDraw = useCallback(createObstaclesDraw(map?.obstacles ?? []), [map]);
 
-    // Get scores from snapshot with type assertion to handle missing type definition
-    type SnapshotWithScore = Snapshot & { score?: Record<string, number> };
-    const snapshotWithScore = snapshot as SnapshotWithScore;
-    const redScore = snapshotWithScore?.score?.['red'] || 0;
-    const blueScore = snapshotWithScore?.score?.['blue'] || 0;
+    const redScore = snapshot?.score?.red || 0;
+    const blueScore = snapshot?.score?.blue || 0;
 
     // Text style for score display
     const scoreStyle = new TextStyle({
@@ -109,14 +125,26 @@ export const Renderer = () => {
                 {/* Arena outline */}
                 <pixiGraphics draw={arenaDraw} />
 
+                {/* Walls */}
+                <pixiGraphics draw={obstaclesDraw} />
+
                 {/* Players */}
                 {snapshot?.players.map((player: Player) => {
-                    const { position, metadata, team } = player;
+                    const { position, metadata, team, connected } = player;
                     const color = teamColor(team);
                     const radius = 2.5; // world units (half of rust Radius for nicer visuals)
                     const drawCb = createPlayerDraw(radius, color);
 
-                    return <pixiGraphics key={metadata.id} x={position.x} y={position.y} draw={drawCb} />;
+                    // Players waiting to reconnect are faded out
+                    return (
+                        <pixiGraphics
+                            key={metadata.id}
+                            x={position.x}
+                            y={position.y}
+                            alpha={connected ? 1 : 0.4}
+                            draw={drawCb}
+                        />
+                    );
                 })}
 
                 {/* Flags */}
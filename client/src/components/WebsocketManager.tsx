@@ -1,8 +1,9 @@
 import { useEffect } from 'react';
 
-import { Snapshot } from '@/bindings';
+import { ClientMessage, Player, Snapshot, SnapshotDelta, WireFormat } from '@/bindings';
+import { decodeMessage } from '@/lib/wire';
 import { useGameStore } from '@/store';
-import { decode } from '@msgpack/msgpack';
+import { encode } from '@msgpack/msgpack';
 
 import { toast } from 'sonner';
 
@@ -11,39 +12,181 @@ if (!WS_URL) {
     throw new Error('NEXT_PUBLIC_WS_URL is not set');
 }
 
+// Same server over plain HTTP, for matchmaking
+const HTTP_URL = WS_URL.replace(/^ws/, 'http');
+
+// Compact unless the named format is asked for, which is easier to read when debugging
+const WIRE_FORMAT: WireFormat = process.env.NEXT_PUBLIC_WIRE_FORMAT === 'named' ? 'named' : 'compact';
+
+// Must match PROTOCOL_VERSION in game/src/public.rs
+const PROTOCOL_VERSION = 7;
+// Close codes from game/src/public.rs, anything from 4000 up means don't reconnect
+const CLOSE_BAD_HANDSHAKE = 4000;
+const CLOSE_PROTOCOL_MISMATCH = 4001;
+const CLOSE_SESSION_REPLACED = 4002;
+const CLOSE_ID_IN_USE = 4003;
+const CLOSE_ROOM_FULL = 4004;
+
+// How long to wait before trying to resume after the connection drops
+const RECONNECT_DELAY = 1000;
+
+// How many received snapshots we keep as possible delta baselines
+const SNAPSHOT_HISTORY = 64;
+
+// Rebuild a full snapshot from a delta and the baseline it was diffed against
+const applyDelta = (delta: SnapshotDelta, baseline: Snapshot): Snapshot => {
+    const changed = new Map(delta.changed_players.map((p) => [p.id, p]));
+    const players = baseline.players
+        .filter((old) => !delta.removed_players.includes(old.metadata.id))
+        .map((old): Player => {
+            const p = changed.get(old.metadata.id);
+            if (!p) return old;
+            return {
+                metadata: old.metadata,
+                position: p.position ?? old.position,
+                velocity: p.velocity ?? old.velocity,
+                team: p.team ?? old.team,
+                melee_active: p.melee_active ?? old.melee_active,
+                stats: p.stats ?? old.stats,
+                connected: p.connected ?? old.connected,
+                last_processed_input: p.last_processed_input ?? old.last_processed_input,
+                last_processed_tick: p.last_processed_tick ?? old.last_processed_tick
+            };
+        });
+    players.push(...delta.added_players);
+
+    return {
+        sequence: delta.sequence,
+        players,
+        flags: baseline.flags.map((old) => delta.flags.find((f) => f.team === old.team) ?? old),
+        score: delta.score ?? baseline.score,
+        phase: delta.phase ?? baseline.phase,
+        time_remaining: delta.time_remaining
+    };
+};
+
+// Where /matchmake wants us, the ticket claims the slot it is holding there
+interface Matchmade {
+    room: string;
+    ticket: string | null;
+}
+
+// Ask for a room the first time, reconnects go back to the same one without a ticket
+const findRoom = async (room: string | null): Promise<Matchmade> => {
+    if (room) return { room, ticket: null };
+
+    const response = await fetch(`${HTTP_URL}/matchmake`);
+    if (!response.ok) {
+        throw new Error(`Matchmaking failed: ${response.status}`);
+    }
+
+    return (await response.json()) as Matchmade;
+};
+
 export const WebsocketManager = () => {
     const ws = useGameStore((state) => state.ws);
     const setWS = useGameStore((state) => state.setWS);
     const setSnapshot = useGameStore((state) => state.setSnapshot);
+    const setMap = useGameStore((state) => state.setMap);
     const clientId = useGameStore((state) => state.clientId);
     const team = useGameStore((state) => state.team);
+    const setRoom = useGameStore((state) => state.setRoom);
+    const setSessionToken = useGameStore((state) => state.setSessionToken);
     useEffect(() => {
-        if (!ws) {
-            const ws = new WebSocket(`${WS_URL}/ws?id=${clientId}&team=${team}`);
+        if (ws) return;
+
+        let cancelled = false;
+        const connect = (room: string, ticket: string | null) => {
+            // Read directly so a new token doesn't reconnect on its own
+            const sessionToken = useGameStore.getState().sessionToken;
+            const session = sessionToken ? `&session=${sessionToken}` : '';
+            const claim = ticket ? `&ticket=${ticket}` : '';
+            const ws = new WebSocket(
+                `${WS_URL}/ws?id=${clientId}&team=${team}&format=${WIRE_FORMAT}&room=${room}${claim}${session}`
+            );
             ws.binaryType = 'arraybuffer';
+            const history: Snapshot[] = [];
+
+            // Store the snapshot and tell the server it can diff against it
+            const receiveSnapshot = (snapshot: Snapshot) => {
+                history.push(snapshot);
+                if (history.length > SNAPSHOT_HISTORY) history.shift();
+                setSnapshot(snapshot);
+
+                const ack: ClientMessage = { ack: { sequence: BigInt(snapshot.sequence) } };
+                ws.send(encode(ack, { useBigInt64: true }));
+            };
 
             ws.onopen = () => {
                 console.log('WebSocket connection opened');
-                toast.success('Connected to server');
+                const hello: ClientMessage = { hello: { protocolVersion: PROTOCOL_VERSION } };
+                ws.send(encode(hello));
             };
 
             ws.onmessage = async (event) => {
                 try {
-                    const snapshot = decode(event.data) as Snapshot;
-                    setSnapshot(snapshot);
+                    const message = decodeMessage(event.data, WIRE_FORMAT);
+                    if ('welcome' in message) {
+                        console.log('Connected to server build', message.welcome.server_build);
+                        toast.success('Connected to server');
+                        setSessionToken(message.welcome.session_token);
+                        setMap(message.welcome.map);
+                    } else if ('snapshot' in message) {
+                        receiveSnapshot(message.snapshot);
+                    } else if ('delta' in message) {
+                        const baseline = history.find(
+                            (old) => Number(old.sequence) === Number(message.delta.baseline)
+                        );
+                        if (!baseline) {
+                            console.warn('Missing baseline for delta', message.delta.baseline);
+                            return;
+                        }
+                        receiveSnapshot(applyDelta(message.delta, baseline));
+                    }
                 } catch (error) {
                     console.error('Error decoding message:', error);
                 }
             };
 
-            ws.onclose = () => {
-                console.log('WebSocket connection closed');
+            ws.onclose = (event) => {
+                console.log('WebSocket connection closed', event.code, event.reason);
+                if (event.code === CLOSE_PROTOCOL_MISMATCH) {
+                    toast.error(`Client is out of date, please refresh (${event.reason})`);
+                } else if (event.code === CLOSE_SESSION_REPLACED) {
+                    toast.warning('Playing in another window, refresh to play here');
+                } else if (event.code === CLOSE_ID_IN_USE) {
+                    toast.error('Someone is already playing with that id');
+                } else if (event.code === CLOSE_ROOM_FULL) {
+                    toast.error('That room is full');
+                } else if (event.code >= CLOSE_BAD_HANDSHAKE) {
+                    toast.error(`Server rejected connection: ${event.reason}`);
+                } else {
+                    // Dropped, the server holds our player for a while so try to resume
+                    toast.warning('Connection lost, reconnecting...');
+                    setTimeout(() => setWS(null), RECONNECT_DELAY);
+                }
             };
 
             setWS(ws);
+        };
+
+        const join = () =>
+            findRoom(useGameStore.getState().room)
+                .then(({ room, ticket }) => {
+                    if (cancelled) return;
+                    setRoom(room);
+                    connect(room, ticket);
+                })
+                .catch((error) => {
+                    console.error('Error finding a room:', error);
+                    toast.error('Could not find a room, retrying...');
+                    setTimeout(() => !cancelled && join(), RECONNECT_DELAY);
+                });
+        join();
 
-            return;
-        }
+        return () => {
+            cancelled = true;
+        };
     }, [ws]);
 
     return null; // headless component
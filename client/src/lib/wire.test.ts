@@ -0,0 +1,16 @@
+import { fromCompact } from './wire.ts';
+import assert from 'node:assert/strict';
+import { readFileSync } from 'node:fs';
+import { test } from 'node:test';
+
+// Written by game/tests/wire.rs: every message both as the compact arrays the client
+// receives and as the named objects they should decode to
+const FIXTURE = new URL('../../../game/tests/fixtures/wire_messages.json', import.meta.url);
+
+const samples: Record<string, { compact: unknown; named: unknown }> = JSON.parse(readFileSync(FIXTURE, 'utf8'));
+
+for (const [name, { compact, named }] of Object.entries(samples)) {
+    test(`compact ${name} decodes to the named shape`, () => {
+        assert.deepStrictEqual(fromCompact(compact), named);
+    });
+}
@@ -0,0 +1,191 @@
+import type { GameMessage, WireFormat } from '@/bindings';
+import { decode } from '@msgpack/msgpack';
+
+// The compact format sends structs as positional arrays in Rust field order.
+// These shapes turn them back into the objects the ts-rs bindings describe,
+// so they must be kept in sync with game/src/public.rs. wire.test.ts checks them against
+// samples written by game/tests/wire.rs.
+type Shape = (value: unknown) => unknown;
+
+const raw: Shape = (value) => value;
+
+const struct =
+    (fields: [string, Shape][]): Shape =>
+    (value) =>
+        Array.isArray(value) ? Object.fromEntries(fields.map(([name, shape], i) => [name, shape(value[i])])) : value;
+
+const list =
+    (shape: Shape): Shape =>
+    (value) =>
+        (value as unknown[]).map(shape);
+
+// Unit variants are plain strings, the rest are { variant: fields }
+const variants =
+    (shapes: Record<string, Shape>): Shape =>
+    (value) => {
+        if (typeof value === 'string') return value;
+        const [[name, inner]] = Object.entries(value as object);
+        return { [name]: (shapes[name] ?? raw)(inner) };
+    };
+
+const position = struct([
+    ['x', raw],
+    ['y', raw]
+]);
+const velocity = struct([
+    ['dx', raw],
+    ['dy', raw]
+]);
+const stats = struct([
+    ['tags', raw],
+    ['times_tagged', raw],
+    ['flag_pickups', raw],
+    ['captures', raw],
+    ['returns', raw],
+    ['carry_time', raw]
+]);
+const player = struct([
+    ['metadata', struct([['id', raw]])],
+    ['position', position],
+    ['velocity', velocity],
+    ['team', raw],
+    ['melee_active', raw],
+    ['stats', stats],
+    ['connected', raw],
+    ['last_processed_input', raw],
+    ['last_processed_tick', raw]
+]);
+const flag = struct([
+    ['position', position],
+    ['team', raw],
+    ['item', struct([['held_by', raw]])],
+    ['auto_return_remaining', raw]
+]);
+const teamBase = struct([
+    ['flag_position', position],
+    ['spawn_positions', list(position)],
+    ['capture_radius', raw],
+    ['max_speed', raw],
+    ['flag_return_seconds', raw]
+]);
+const mapConfig = struct([
+    ['name', raw],
+    ['width', raw],
+    ['height', raw],
+    ['red', teamBase],
+    ['blue', teamBase],
+    [
+        'zones',
+        list(
+            struct([
+                ['name', raw],
+                ['min', position],
+                ['max', position]
+            ])
+        )
+    ],
+    [
+        'obstacles',
+        list(
+            variants({
+                rect: struct([
+                    ['min', position],
+                    ['max', position]
+                ]),
+                circle: struct([
+                    ['center', position],
+                    ['radius', raw]
+                ])
+            })
+        )
+    ]
+]);
+const snapshot = struct([
+    ['sequence', raw],
+    ['players', list(player)],
+    ['flags', list(flag)],
+    ['score', raw],
+    ['phase', raw],
+    ['time_remaining', raw]
+]);
+const playerDelta = struct([
+    ['id', raw],
+    ['position', position],
+    ['velocity', velocity],
+    ['team', raw],
+    ['melee_active', raw],
+    ['stats', stats],
+    ['connected', raw],
+    ['last_processed_input', raw],
+    ['last_processed_tick', raw]
+]);
+const snapshotDelta = struct([
+    ['sequence', raw],
+    ['baseline', raw],
+    ['added_players', list(player)],
+    ['changed_players', list(playerDelta)],
+    ['removed_players', raw],
+    ['flags', list(flag)],
+    ['score', raw],
+    ['phase', raw],
+    ['time_remaining', raw]
+]);
+const flagEvent = struct([
+    ['team', raw],
+    ['playerId', raw]
+]);
+const teamEvent = struct([
+    ['id', raw],
+    ['team', raw]
+]);
+const gameEvent = variants({
+    playerTagged: struct([
+        ['attacker', raw],
+        ['victim', raw]
+    ]),
+    flagPickedUp: flagEvent,
+    flagDropped: flagEvent,
+    flagReturned: flagEvent,
+    flagCaptured: flagEvent,
+    playerJoined: teamEvent,
+    playerLeft: struct([['id', raw]]),
+    playerDisconnected: struct([['id', raw]]),
+    playerReconnected: struct([['id', raw]]),
+    playerChangedTeam: teamEvent,
+    matchPhaseChanged: struct([['phase', raw]]),
+    matchFinished: struct([
+        ['score', raw],
+        [
+            'scoreboard',
+            list(
+                struct([
+                    ['id', raw],
+                    ['team', raw],
+                    ['stats', stats]
+                ])
+            )
+        ]
+    ])
+});
+const welcome = struct([
+    ['protocol_version', raw],
+    ['server_build', raw],
+    ['player_id', raw],
+    ['session_token', raw],
+    ['tick_rate', raw],
+    ['map', mapConfig]
+]);
+const gameMessage = variants({
+    welcome: welcome,
+    snapshot: snapshot,
+    delta: snapshotDelta,
+    events: list(gameEvent)
+});
+
+// A decoded compact message as the objects the named format would have given
+export const fromCompact = (message: unknown): GameMessage => gameMessage(message) as GameMessage;
+
+export const decodeMessage = (data: ArrayBuffer, format: WireFormat): GameMessage => {
+    const message = decode(data);
+    return format === 'compact' ? fromCompact(message) : (message as GameMessage);
+};
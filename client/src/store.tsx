@@ -1,4 +1,5 @@
-import { Snapshot, Team } from '@/bindings';
+import { ClientInput, ClientMessage, InputEnvelope, MapConfig, Snapshot, Team } from '@/bindings';
+import { encode } from '@msgpack/msgpack';
 
 import { create } from 'zustand';
 
@@ -7,14 +8,27 @@ interface GameStore {
     // NETWORK
     ws: WebSocket | null;
     snapshot: Snapshot | null;
+    map: MapConfig | null;
     clientId: string;
     team: Team;
+    // Picked by /matchmake on the first connect, kept so reconnects go back to it
+    room: string | null;
+    // From the server's welcome, used to resume the same player after a drop
+    sessionToken: string | null;
+    // Inputs sent but not yet in a snapshot, replayed on top of it when predicting
+    pendingInputs: InputEnvelope[];
+    // Kept across reconnects so the server never sees the sequence go backwards
+    nextInputSequence: number;
 
     // FUNCTIONS
-    setWS: (ws: WebSocket) => void;
+    setWS: (ws: WebSocket | null) => void;
     setSnapshot: (snapshot: Snapshot) => void;
+    setMap: (map: MapConfig) => void;
     setClientId: (clientId: string) => void;
     setTeam: (team: Team) => void;
+    setRoom: (room: string) => void;
+    setSessionToken: (sessionToken: string) => void;
+    sendInput: (input: ClientInput) => void;
     reset: () => void;
 }
 
@@ -28,18 +42,48 @@ const initialState = {
     // NETWORK
     ws: null,
     snapshot: null,
+    map: null,
     clientId: generateRandomId(),
-    team: 'red'
+    team: 'red',
+    room: null,
+    sessionToken: null,
+    pendingInputs: [],
+    nextInputSequence: 1
 } as GameStore;
 
-export const useGameStore = create<GameStore>()((set) => ({
+export const useGameStore = create<GameStore>()((set, get) => ({
     ...initialState,
 
     // NETWORK
     setWS: (ws) => set({ ws }),
-    setSnapshot: (snapshot) => set({ snapshot }),
+    setSnapshot: (snapshot) =>
+        set((state) => {
+            // Drop whatever the server has already applied
+            const me = snapshot.players.find((player) => player.metadata.id === state.clientId);
+            const processed = me?.last_processed_input ?? 0;
+            return {
+                snapshot,
+                pendingInputs: state.pendingInputs.filter((envelope) => envelope.sequence > processed)
+            };
+        }),
+    setMap: (map) => set({ map }),
     setClientId: (clientId) => set({ clientId }),
     setTeam: (team) => set({ team }),
+    setRoom: (room) => set({ room }),
+    setSessionToken: (sessionToken) => set({ sessionToken }),
+    sendInput: (input) => {
+        const { ws, snapshot, pendingInputs, nextInputSequence } = get();
+        if (!ws || ws.readyState !== WebSocket.OPEN) return;
+
+        const envelope: InputEnvelope = {
+            sequence: nextInputSequence,
+            client_tick: BigInt(snapshot?.sequence ?? 0),
+            input
+        };
+        const msg: ClientMessage = { input: envelope };
+        ws.send(encode(msg, { useBigInt64: true }));
+        set({ pendingInputs: [...pendingInputs, envelope], nextInputSequence: nextInputSequence + 1 });
+    },
 
     // Reset to initial state
     reset: () => set({ ...initialState, clientId: generateRandomId() })
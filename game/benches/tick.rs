@@ -0,0 +1,80 @@
+// Time per Game::step as the player count grows, against the server's tick budget.
+// Everyone runs around and swings during a live match so melee, flags and collisions all do work.
+// Run with `cargo bench -p game`
+
+use std::time::{Duration, Instant};
+
+use game::{Game, Input, TICK_MILLIS, Team, Velocity};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+const TICKS: u32 = 250;
+const PLAYER_COUNTS: [usize; 5] = [50, 100, 200, 300, 500];
+
+fn live_game(players: usize) -> Game {
+    common::live_game((0..players).map(|i| {
+        let team = if i % 2 == 0 { Team::Red } else { Team::Blue };
+        (format!("player-{}", i), team)
+    }))
+}
+
+fn main() {
+    let budget = Duration::from_millis(TICK_MILLIS);
+
+    println!("{} ticks per run, {:?} budget per tick", TICKS, budget);
+    println!(
+        "{:>8} {:>12} {:>12} {:>10}",
+        "players", "mean", "worst", "of budget"
+    );
+
+    for players in PLAYER_COUNTS {
+        let mut game = live_game(players);
+        let mut total = Duration::ZERO;
+        let mut worst = Duration::ZERO;
+
+        for tick in 0..TICKS {
+            // New heading every half second, spread so players cross each other
+            if tick % 25 == 0 {
+                for i in 0..players {
+                    let angle = (i as f32 * 0.37 + tick as f32 * 0.11).sin() * std::f32::consts::PI;
+                    let _ = game.apply_input(Input::PlayerMove {
+                        player_id: format!("player-{}", i),
+                        velocity: Velocity {
+                            dx: angle.cos() * 40.0,
+                            dy: angle.sin() * 40.0,
+                        },
+                        sequence: tick + 1,
+                        client_tick: tick as u64,
+                    });
+                }
+            }
+            if tick % 25 == 10 {
+                for i in (0..players).step_by(3) {
+                    let _ = game.apply_input(Input::PlayerMelee {
+                        player_id: format!("player-{}", i),
+                        sequence: tick + 1,
+                        client_tick: tick as u64,
+                    });
+                }
+            }
+
+            let start = Instant::now();
+            game.step();
+            let elapsed = start.elapsed();
+            std::hint::black_box(game.drain_events());
+
+            total += elapsed;
+            worst = worst.max(elapsed);
+        }
+
+        let mean = total / TICKS;
+        println!(
+            "{:>8} {:>9.1} us {:>9.1} us {:>9.1}%",
+            players,
+            mean.as_secs_f64() * 1e6,
+            worst.as_secs_f64() * 1e6,
+            mean.as_secs_f64() / budget.as_secs_f64() * 100.0,
+        );
+    }
+}
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { InputEnvelope } from "./InputEnvelope";
+
+export type ClientMessage = { "hello": { protocolVersion: number, } } | { "input": InputEnvelope } | { "ack": { sequence: bigint, } };
@@ -3,4 +3,4 @@ import type { Item } from "./Item";
 import type { Position } from "./Position";
 import type { Team } from "./Team";
 
-export type Flag = { position: Position, team: Team, item: Item, };
+export type Flag = { position: Position, team: Team, item: Item, auto_return_remaining: number | null, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { MatchPhase } from "./MatchPhase";
+import type { ScoreboardEntry } from "./ScoreboardEntry";
+import type { Team } from "./Team";
+
+export type GameEvent = { "playerTagged": { attacker: string, victim: string, } } | { "flagPickedUp": { team: Team, playerId: string, } } | { "flagDropped": { team: Team, playerId: string, } } | { "flagReturned": { team: Team, playerId: string | null, } } | { "flagCaptured": { team: Team, playerId: string, } } | { "playerJoined": { id: string, team: Team, } } | { "playerLeft": { id: string, } } | { "playerDisconnected": { id: string, } } | { "playerReconnected": { id: string, } } | { "playerChangedTeam": { id: string, team: Team, } } | { "matchPhaseChanged": { phase: MatchPhase, } } | { "matchFinished": { score: { [key in Team]?: number }, scoreboard: Array<ScoreboardEntry>, } };
@@ -1,5 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
-import type { PlayerAssigned } from "./PlayerAssigned";
+import type { GameEvent } from "./GameEvent";
 import type { Snapshot } from "./Snapshot";
+import type { SnapshotDelta } from "./SnapshotDelta";
+import type { Welcome } from "./Welcome";
 
-export type GameMessage = { "Snapshot": Snapshot } | { "PlayerAssigned": PlayerAssigned };
+export type GameMessage = { "welcome": Welcome } | { "snapshot": Snapshot } | { "delta": SnapshotDelta } | { "events": Array<GameEvent> };
@@ -2,4 +2,4 @@
 import type { Team } from "./Team";
 import type { Velocity } from "./Velocity";
 
-export type Input = { "createPlayer": { id: string, team: Team, } } | { "playerMove": { playerId: string, velocity: Velocity, } } | { "removePlayer": { id: string, } } | { "playerMelee": { player_id: string, } };
+export type Input = { "createPlayer": { id: string, team: Team, } } | { "playerMove": { playerId: string, velocity: Velocity, sequence: number, clientTick: bigint, } } | { "removePlayer": { id: string, } } | { "playerMelee": { player_id: string, sequence: number, client_tick: bigint, } } | { "changeTeam": { id: string, team: Team, } } | { "playerDisconnected": { id: string, } } | { "playerReconnected": { id: string, } };
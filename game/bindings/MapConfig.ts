@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Obstacle } from "./Obstacle";
+import type { TeamBase } from "./TeamBase";
+import type { Zone } from "./Zone";
+
+export type MapConfig = { name: string, width: number, height: number, red: TeamBase, blue: TeamBase, zones: Array<Zone>, obstacles: Array<Obstacle>, };
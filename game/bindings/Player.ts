@@ -1,7 +1,8 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { Metadata } from "./Metadata";
 import type { Position } from "./Position";
+import type { Stats } from "./Stats";
 import type { Team } from "./Team";
 import type { Velocity } from "./Velocity";
 
-export type Player = { metadata: Metadata, position: Position, velocity: Velocity, team: Team, melee_active: boolean, };
+export type Player = { metadata: Metadata, position: Position, velocity: Velocity, team: Team, melee_active: boolean, stats: Stats, connected: boolean, last_processed_input: number, last_processed_tick: bigint, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Position } from "./Position";
+import type { Stats } from "./Stats";
+import type { Team } from "./Team";
+import type { Velocity } from "./Velocity";
+
+export type PlayerDelta = { id: string, position: Position | null, velocity: Velocity | null, team: Team | null, melee_active: boolean | null, stats: Stats | null, connected: boolean | null, last_processed_input: number | null, last_processed_tick: bigint | null, };
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Stats } from "./Stats";
+import type { Team } from "./Team";
+
+export type ScoreboardEntry = { id: string, team: Team, stats: Stats, };
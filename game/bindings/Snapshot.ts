@@ -1,5 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { Flag } from "./Flag";
+import type { MatchPhase } from "./MatchPhase";
 import type { Player } from "./Player";
+import type { Team } from "./Team";
 
-export type Snapshot = { players: Array<Player>, flags: Array<Flag>, };
+export type Snapshot = { sequence: bigint, players: Array<Player>, flags: Array<Flag>, score: { [key in Team]?: number }, phase: MatchPhase, time_remaining: number, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Flag } from "./Flag";
+import type { MatchPhase } from "./MatchPhase";
+import type { Player } from "./Player";
+import type { PlayerDelta } from "./PlayerDelta";
+import type { Team } from "./Team";
+
+export type SnapshotDelta = { sequence: bigint, baseline: bigint, added_players: Array<Player>, changed_players: Array<PlayerDelta>, removed_players: Array<string>, flags: Array<Flag>, score: { [key in Team]?: number } | null, phase: MatchPhase | null, time_remaining: number, };
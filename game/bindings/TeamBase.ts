@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Position } from "./Position";
+
+export type TeamBase = { flag_position: Position, spawn_positions: Array<Position>, capture_radius: number, max_speed: number, flag_return_seconds: number, };
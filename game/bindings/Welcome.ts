@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { MapConfig } from "./MapConfig";
+
+export type Welcome = { protocol_version: number, server_build: string, player_id: string, session_token: string, tick_rate: number, map: MapConfig, };
@@ -1,13 +1,29 @@
+export * from './AutoReturn';
+export * from './ClientInput';
+export * from './ClientMessage';
 export * from './Flag';
+export * from './GameEvent';
 export * from './GameMessage';
 export * from './Input';
+export * from './InputEnvelope';
 export * from './Item';
+export * from './MapConfig';
+export * from './MatchPhase';
 export * from './Melee';
 export * from './Metadata';
+export * from './Obstacle';
 export * from './Player';
 export * from './PlayerAssigned';
+export * from './PlayerDelta';
 export * from './Position';
 export * from './Radius';
+export * from './ScoreboardEntry';
 export * from './Snapshot';
+export * from './SnapshotDelta';
+export * from './Stats';
 export * from './Team';
+export * from './TeamBase';
 export * from './Velocity';
+export * from './Welcome';
+export * from './WireFormat';
+export * from './Zone';
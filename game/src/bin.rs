@@ -1,4 +1,4 @@
-use game::{Game, Input, Snapshot, Velocity};
+use game::{FixedTimestep, Game, Input, MapConfig, Snapshot, Velocity};
 
 use std::{
     thread,
@@ -7,34 +7,40 @@ use std::{
 
 fn main() {
     // Create game
-    let mut game = Game::new();
+    let mut game = Game::new(MapConfig::default());
 
     // Add player
     let player_id1 = "Player1".to_string();
     game.apply_input(Input::CreatePlayer {
         id: player_id1.clone(),
         team: game::Team::Red,
-    });
+    })
+    .unwrap();
 
     // Set initial velocity for player1 (moving toward blue flag)
     game.apply_input(Input::PlayerMove {
         velocity: Velocity { dx: 1.0, dy: 1.0 },
         player_id: player_id1.clone(),
+        sequence: 1,
+        client_tick: 0,
     })
     .unwrap();
 
     // Simple game loop
+    let mut timestep = FixedTimestep::default();
     let mut last_time = Instant::now();
     let mut frame_count = 0;
 
     loop {
-        // Calculate delta time
+        // Work out how many fixed ticks the wall clock owes us
         let current_time = Instant::now();
-        let dt = current_time.duration_since(last_time).as_secs_f32();
+        let due = timestep.advance(current_time.duration_since(last_time));
         last_time = current_time;
 
         // Update game state
-        game.step(dt);
+        for _ in 0..due {
+            game.step();
+        }
 
         // Create a snapshot of the current game state
         let snapshot = game.make_snapshot();
@@ -44,7 +50,7 @@ fn main() {
         println!("Serialized snapshot size: {} bytes", buf.len());
 
         let owned = rmp_serde::from_slice::<Snapshot>(&buf).unwrap();
-        dbg!(&owned.players[0].position);
+        dbg!(game.tick, &owned.players[0].position);
 
         frame_count += 1;
 
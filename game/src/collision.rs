@@ -0,0 +1,117 @@
+// Geometry helpers for resolving circles against static obstacles and each other
+
+use crate::{Obstacle, Position};
+
+// Where an entity started the current tick, used to sweep collisions over its motion
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PreviousPosition {
+    pub position: Position,
+}
+
+// Whether two circles moving in straight lines over the tick touch at any point.
+// Works in b's frame of reference, so it reduces to a segment against a circle.
+pub(crate) fn swept_circles_collide(
+    a_from: Position,
+    a_to: Position,
+    b_from: Position,
+    b_to: Position,
+    radius_sum: f32,
+) -> bool {
+    // Relative position at the start of the tick and relative motion over it
+    let px = a_from.x - b_from.x;
+    let py = a_from.y - b_from.y;
+    let dx = (a_to.x - a_from.x) - (b_to.x - b_from.x);
+    let dy = (a_to.y - a_from.y) - (b_to.y - b_from.y);
+
+    // Time of closest approach, clamped to this tick
+    let motion_sq = dx * dx + dy * dy;
+    let t = if motion_sq > 0.0 {
+        (-(px * dx + py * dy) / motion_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    let cx = px + dx * t;
+    let cy = py + dy * t;
+
+    cx * cx + cy * cy < radius_sum * radius_sum
+}
+
+// Returns where a circle has to be moved to stop overlapping the obstacle,
+// or None if they don't overlap
+pub(crate) fn push_out_of_obstacle(
+    center: Position,
+    radius: f32,
+    obstacle: &Obstacle,
+) -> Option<Position> {
+    match obstacle {
+        Obstacle::Circle {
+            center: obstacle_center,
+            radius: obstacle_radius,
+        } => {
+            let dx = center.x - obstacle_center.x;
+            let dy = center.y - obstacle_center.y;
+            let dist = (dx * dx + dy * dy).sqrt();
+            let min_dist = radius + obstacle_radius;
+
+            if dist >= min_dist {
+                return None;
+            }
+
+            // Dead center, pick an arbitrary direction
+            if dist == 0.0 {
+                return Some(Position {
+                    x: obstacle_center.x + min_dist,
+                    y: obstacle_center.y,
+                });
+            }
+
+            Some(Position {
+                x: obstacle_center.x + dx / dist * min_dist,
+                y: obstacle_center.y + dy / dist * min_dist,
+            })
+        }
+        Obstacle::Rect { min, max } => {
+            let closest = Position {
+                x: center.x.clamp(min.x, max.x),
+                y: center.y.clamp(min.y, max.y),
+            };
+
+            let dx = center.x - closest.x;
+            let dy = center.y - closest.y;
+            let dist_sq = dx * dx + dy * dy;
+
+            if dist_sq >= radius * radius {
+                return None;
+            }
+
+            if dist_sq > 0.0 {
+                let dist = dist_sq.sqrt();
+                return Some(Position {
+                    x: closest.x + dx / dist * radius,
+                    y: closest.y + dy / dist * radius,
+                });
+            }
+
+            // Center is inside the rectangle, push out through the nearest side
+            let left = center.x - min.x;
+            let right = max.x - center.x;
+            let top = center.y - min.y;
+            let bottom = max.y - center.y;
+            let nearest = left.min(right).min(top).min(bottom);
+
+            let mut resolved = center;
+            if nearest == left {
+                resolved.x = min.x - radius;
+            } else if nearest == right {
+                resolved.x = max.x + radius;
+            } else if nearest == top {
+                resolved.y = min.y - radius;
+            } else {
+                resolved.y = max.y + radius;
+            }
+
+            Some(resolved)
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// Snapshot delta compression, only what changed since the client's last acked snapshot is sent
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{Player, PlayerDelta, Snapshot, SnapshotDelta};
+
+// Players keyed by id, so diffing stays linear in the player count
+fn by_id(players: &[Player]) -> HashMap<&str, &Player> {
+    players
+        .iter()
+        .map(|player| (player.metadata.id.as_str(), player))
+        .collect()
+}
+
+// Some(field) only when it differs from the baseline's
+fn changed<T: PartialEq + Clone>(old: &T, new: &T) -> Option<T> {
+    (old != new).then(|| new.clone())
+}
+
+impl Player {
+    // None if nothing about the player changed
+    fn diff(&self, old: &Player) -> Option<PlayerDelta> {
+        if self == old {
+            return None;
+        }
+
+        Some(PlayerDelta {
+            id: self.metadata.id.clone(),
+            position: changed(&old.position, &self.position),
+            velocity: changed(&old.velocity, &self.velocity),
+            team: changed(&old.team, &self.team),
+            melee_active: changed(&old.melee_active, &self.melee_active),
+            stats: changed(&old.stats, &self.stats),
+            connected: changed(&old.connected, &self.connected),
+            last_processed_input: changed(&old.last_processed_input, &self.last_processed_input),
+            last_processed_tick: changed(&old.last_processed_tick, &self.last_processed_tick),
+        })
+    }
+}
+
+impl PlayerDelta {
+    fn apply(&self, old: &Player) -> Player {
+        Player {
+            metadata: old.metadata.clone(),
+            position: self.position.unwrap_or(old.position),
+            velocity: self.velocity.unwrap_or(old.velocity),
+            team: self.team.unwrap_or(old.team),
+            melee_active: self.melee_active.unwrap_or(old.melee_active),
+            stats: self.stats.unwrap_or(old.stats),
+            connected: self.connected.unwrap_or(old.connected),
+            last_processed_input: self
+                .last_processed_input
+                .unwrap_or(old.last_processed_input),
+            last_processed_tick: self.last_processed_tick.unwrap_or(old.last_processed_tick),
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn diff(&self, baseline: &Snapshot) -> SnapshotDelta {
+        let old_players = by_id(&baseline.players);
+        let mut added_players = Vec::new();
+        let mut changed_players = Vec::new();
+        for player in &self.players {
+            match old_players.get(player.metadata.id.as_str()) {
+                Some(old) => changed_players.extend(player.diff(old)),
+                None => added_players.push(player.clone()),
+            }
+        }
+
+        let current_ids: HashSet<&str> = self
+            .players
+            .iter()
+            .map(|player| player.metadata.id.as_str())
+            .collect();
+        let removed_players = baseline
+            .players
+            .iter()
+            .filter(|old| !current_ids.contains(old.metadata.id.as_str()))
+            .map(|old| old.metadata.id.clone())
+            .collect();
+
+        let flags = self
+            .flags
+            .iter()
+            .filter(|flag| !baseline.flags.iter().any(|old| old == *flag))
+            .cloned()
+            .collect();
+
+        SnapshotDelta {
+            sequence: self.sequence,
+            baseline: baseline.sequence,
+            added_players,
+            changed_players,
+            removed_players,
+            flags,
+            score: (self.score != baseline.score).then(|| self.score.clone()),
+            phase: (self.phase != baseline.phase).then_some(self.phase),
+            time_remaining: self.time_remaining,
+        }
+    }
+}
+
+impl SnapshotDelta {
+    // Rebuild the full snapshot from the baseline it was diffed against.
+    // Players keep the baseline's order, anyone new goes on the end.
+    pub fn apply(&self, baseline: &Snapshot) -> Snapshot {
+        let changed: HashMap<&str, &PlayerDelta> = self
+            .changed_players
+            .iter()
+            .map(|delta| (delta.id.as_str(), delta))
+            .collect();
+        let removed: HashSet<&str> = self.removed_players.iter().map(String::as_str).collect();
+
+        let mut players: Vec<_> = baseline
+            .players
+            .iter()
+            .filter(|old| !removed.contains(old.metadata.id.as_str()))
+            .map(|old| match changed.get(old.metadata.id.as_str()) {
+                Some(delta) => delta.apply(old),
+                None => old.clone(),
+            })
+            .collect();
+        players.extend(self.added_players.iter().cloned());
+        let flags = baseline
+            .flags
+            .iter()
+            .map(|old| {
+                self.flags
+                    .iter()
+                    .find(|flag| flag.team == old.team)
+                    .unwrap_or(old)
+                    .clone()
+            })
+            .collect();
+
+        Snapshot {
+            sequence: self.sequence,
+            players,
+            flags,
+            score: self.score.clone().unwrap_or_else(|| baseline.score.clone()),
+            phase: self.phase.unwrap_or(baseline.phase),
+            time_remaining: self.time_remaining,
+        }
+    }
+}
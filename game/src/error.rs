@@ -0,0 +1,36 @@
+// Errors from applying input to the game, none of these should take down a room
+
+use std::fmt;
+
+use hecs::ComponentError;
+
+use crate::Velocity;
+
+#[derive(Debug)]
+pub enum GameError {
+    // Usually input that arrived just after the player left
+    UnknownPlayer(String),
+    DuplicatePlayer(String),
+    // NaN or infinite velocity, rejected rather than clamped
+    InvalidVelocity(Velocity),
+    Component(ComponentError),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::UnknownPlayer(id) => write!(f, "unknown player: {}", id),
+            GameError::DuplicatePlayer(id) => write!(f, "player already exists: {}", id),
+            GameError::InvalidVelocity(velocity) => write!(f, "invalid velocity: {:?}", velocity),
+            GameError::Component(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl From<ComponentError> for GameError {
+    fn from(e: ComponentError) -> Self {
+        GameError::Component(e)
+    }
+}
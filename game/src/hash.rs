@@ -0,0 +1,140 @@
+// Hash of the whole simulation state, two games fed the same inputs must agree on it every tick
+
+use crate::collision::PreviousPosition;
+use crate::{
+    AutoReturn, Disconnected, Game, Item, LastProcessedInput, Melee, Metadata, Position, Radius,
+    Stats, Team, Velocity,
+};
+
+// FNV-1a over explicit little-endian bytes, unlike the std hashers it gives the same
+// answer on every run, platform and Rust version
+struct StateHash(u64);
+
+impl StateHash {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn int(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    // Bit pattern, not value, so -0.0 and 0.0 count as different states
+    fn float(&mut self, value: f32) {
+        self.bytes(&value.to_bits().to_le_bytes());
+    }
+
+    fn flag(&mut self, value: bool) {
+        self.int(value as u64);
+    }
+
+    fn text(&mut self, value: &str) {
+        self.int(value.len() as u64);
+        self.bytes(value.as_bytes());
+    }
+
+    fn position(&mut self, position: &Position) {
+        self.float(position.x);
+        self.float(position.y);
+    }
+}
+
+impl Game {
+    pub fn world_hash(&self) -> u64 {
+        let mut hash = StateHash::new();
+        hash.int(self.tick);
+        hash.int(self.phase as u64);
+        hash.float(self.phase_time_remaining);
+        for team in [Team::Red, Team::Blue] {
+            hash.int(self.score.get(&team).copied().unwrap_or(0) as u64);
+            hash.int(self.spawn_cursor.get(&team).copied().unwrap_or(0) as u64);
+        }
+
+        // Each player hashed on its own and combined by id, storage order isn't state
+        let mut players = self
+            .world
+            .query::<(
+                &Metadata,
+                &Position,
+                &PreviousPosition,
+                &Velocity,
+                &Radius,
+                &Team,
+                &Melee,
+                &Stats,
+                &LastProcessedInput,
+                Option<&Disconnected>,
+            )>()
+            .into_iter()
+            .map(
+                |(
+                    _,
+                    (
+                        metadata,
+                        position,
+                        previous,
+                        velocity,
+                        radius,
+                        team,
+                        melee,
+                        stats,
+                        last_input,
+                        disconnected,
+                    ),
+                )| {
+                    let mut player = StateHash::new();
+                    player.position(position);
+                    player.position(&previous.position);
+                    player.float(velocity.dx);
+                    player.float(velocity.dy);
+                    player.float(radius.value);
+                    player.int(*team as u64);
+                    player.flag(melee.active);
+                    player.float(melee.cooldown);
+                    player.float(melee.max_cooldown);
+                    player.int(stats.tags as u64);
+                    player.int(stats.times_tagged as u64);
+                    player.int(stats.flag_pickups as u64);
+                    player.int(stats.captures as u64);
+                    player.int(stats.returns as u64);
+                    player.float(stats.carry_time);
+                    player.int(last_input.sequence as u64);
+                    player.int(last_input.client_tick);
+                    player.flag(disconnected.is_some());
+                    (metadata.id.clone(), player.0)
+                },
+            )
+            .collect::<Vec<_>>();
+        players.sort_unstable();
+
+        hash.int(players.len() as u64);
+        for (id, player) in players {
+            hash.text(&id);
+            hash.int(player);
+        }
+
+        for flag in [self.red_flag, self.blue_flag] {
+            if let Ok(position) = self.world.get::<&Position>(flag) {
+                hash.position(&position);
+            }
+            if let Ok(item) = self.world.get::<&Item>(flag) {
+                hash.flag(item.held_by.is_some());
+                hash.text(item.held_by.as_deref().unwrap_or_default());
+            }
+            if let Ok(auto_return) = self.world.get::<&AutoReturn>(flag) {
+                hash.flag(auto_return.remaining.is_some());
+                hash.float(auto_return.remaining.unwrap_or_default());
+                hash.float(auto_return.duration);
+            }
+        }
+
+        hash.0
+    }
+}
@@ -0,0 +1,27 @@
+// Validation of client-supplied input before it touches the world
+
+use crate::{GameError, Velocity};
+
+impl Velocity {
+    // Scales the velocity down to max_speed, keeping its direction
+    pub fn clamped(self, max_speed: f32) -> Result<Velocity, GameError> {
+        if !self.dx.is_finite() || !self.dy.is_finite() {
+            return Err(GameError::InvalidVelocity(self));
+        }
+
+        // Not hypot, it comes from the platform's libm and can round differently in wasm.
+        // sqrt is exact everywhere, and an overflow to infinity just takes the slow path.
+        if (self.dx * self.dx + self.dy * self.dy).sqrt() <= max_speed {
+            return Ok(self);
+        }
+
+        // Divide by the larger component first so the length can't overflow
+        let largest = self.dx.abs().max(self.dy.abs());
+        let (dx, dy) = (self.dx / largest, self.dy / largest);
+        let scale = max_speed / (dx * dx + dy * dy).sqrt();
+        Ok(Velocity {
+            dx: dx * scale,
+            dy: dy * scale,
+        })
+    }
+}
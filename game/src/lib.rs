@@ -1,8 +1,36 @@
-use hecs::{ComponentError, Entity, NoSuchEntity, World};
+use hecs::{Entity, World};
 use std::collections::HashMap;
 
+mod collision;
+mod delta;
+mod error;
+mod hash;
+mod input;
+pub mod map;
+mod spatial;
+mod timestep;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod wire;
+
+use collision::PreviousPosition;
+use spatial::SpatialGrid;
 pub mod public;
+pub use error::GameError;
+pub use map::MapError;
 pub use public::*;
+pub use timestep::{FixedTimestep, MAX_CATCH_UP_TICKS};
+#[cfg(feature = "wasm")]
+pub use wasm::WasmGame;
+// Marks a player whose connection dropped but may still come back
+pub(crate) struct Disconnected;
+// Newest client input applied to a player, echoed back in snapshots for reconciliation
+#[derive(Clone, Copy)]
+pub(crate) struct LastProcessedInput {
+    sequence: u32,
+    client_tick: u64,
+}
+
 // Game struct that uses hecs ECS
 pub struct Game {
     pub world: World,
@@ -10,57 +38,244 @@ pub struct Game {
     pub blue_flag: Entity,
     pub player_map: HashMap<String, Entity>,
     pub score: HashMap<Team, u32>,
+    pub phase: MatchPhase,
+    // Seconds left in the current phase
+    pub phase_time_remaining: f32,
+    // Number of steps taken so far
+    pub tick: u64,
+    pub map: MapConfig,
+    // Next spawn point to hand out per team
+    spawn_cursor: HashMap<Team, usize>,
+    // Rebuilt every tick from entity positions
+    broadphase: SpatialGrid,
+    // Events since the last drain_events
+    events: Vec<GameEvent>,
 }
 
-const GRID_X: f32 = 200.0;
-const GRID_Y: f32 = 100.0;
-
-struct TeamConfig {
-    pub flag_position: Position,
-    pub spawn_position: Position,
-}
-
-const RED_TEAM: TeamConfig = TeamConfig {
-    flag_position: Position { x: 10.0, y: 50.0 },
-    spawn_position: Position { x: 5.0, y: 5.0 },
-};
+// Smallest and largest number of sub-steps used to sweep movement against walls
+const MIN_SWEEP_STEP: f32 = 0.1;
+const MAX_SWEEP_STEPS: u32 = 256;
 
-const BLUE_TEAM: TeamConfig = TeamConfig {
-    flag_position: Position { x: 190.0, y: 50.0 },
-    spawn_position: Position { x: 195.0, y: 95.0 },
-};
+// Roughly one player across
+const BROADPHASE_CELL_SIZE: f32 = 10.0;
 
 impl Game {
-    pub fn new() -> Self {
+    pub fn new(map: MapConfig) -> Self {
         let mut world = World::new();
 
         // Create flags
         let red_flag = world.spawn((
             Item { held_by: None },
-            Position {
-                x: RED_TEAM.flag_position.x,
-                y: RED_TEAM.flag_position.y,
-            },
+            map.red.flag_position,
             Team::Red,
             Radius { value: 5.0 },
+            AutoReturn {
+                remaining: None,
+                duration: map.red.flag_return_seconds,
+            },
         ));
 
         let blue_flag = world.spawn((
             Item { held_by: None },
-            Position {
-                x: BLUE_TEAM.flag_position.x,
-                y: BLUE_TEAM.flag_position.y,
-            },
+            map.blue.flag_position,
             Team::Blue,
             Radius { value: 5.0 },
+            AutoReturn {
+                remaining: None,
+                duration: map.blue.flag_return_seconds,
+            },
         ));
 
+        // Create walls
+        for obstacle in &map.obstacles {
+            world.spawn((*obstacle,));
+        }
+
         Self {
             world,
             red_flag,
             blue_flag,
             player_map: HashMap::new(),
             score: HashMap::from([(Team::Red, 0), (Team::Blue, 0)]),
+            phase: MatchPhase::Warmup,
+            phase_time_remaining: WARMUP_DURATION,
+            tick: 0,
+            map,
+            spawn_cursor: HashMap::new(),
+            broadphase: SpatialGrid::new(BROADPHASE_CELL_SIZE),
+            events: Vec::new(),
+        }
+    }
+
+    // Take everything that happened since the last call
+    pub fn drain_events(&mut self) -> Vec<GameEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Every player's stats, best first
+    pub fn scoreboard(&self) -> Vec<ScoreboardEntry> {
+        let mut scoreboard = self
+            .world
+            .query::<(&Metadata, &Team, &Stats)>()
+            .into_iter()
+            .map(|(_, (metadata, team, stats))| ScoreboardEntry {
+                id: metadata.id.clone(),
+                team: *team,
+                stats: *stats,
+            })
+            .collect::<Vec<_>>();
+
+        scoreboard.sort_by(|a, b| {
+            b.stats
+                .captures
+                .cmp(&a.stats.captures)
+                .then(b.stats.tags.cmp(&a.stats.tags))
+                .then(a.id.cmp(&b.id))
+        });
+
+        scoreboard
+    }
+
+    fn update_stats(&mut self, entity: Entity, update: impl FnOnce(&mut Stats)) {
+        if let Ok(mut stats) = self.world.get::<&mut Stats>(entity) {
+            update(&mut stats);
+        }
+    }
+
+    fn set_phase(&mut self, phase: MatchPhase) {
+        self.phase = phase;
+        self.events.push(GameEvent::MatchPhaseChanged { phase });
+        self.phase_time_remaining = match phase {
+            MatchPhase::Warmup => WARMUP_DURATION,
+            MatchPhase::Live => MATCH_DURATION,
+            MatchPhase::Overtime => OVERTIME_DURATION,
+            MatchPhase::Finished => GAME_OVER_DURATION,
+        };
+
+        match phase {
+            MatchPhase::Live => {
+                // Start the match from a clean slate
+                self.score = HashMap::from([(Team::Red, 0), (Team::Blue, 0)]);
+                self.return_flag(Team::Red);
+                self.return_flag(Team::Blue);
+
+                // Sorted so spawn points go out in the same order every run
+                let mut players = self.player_map.values().copied().collect::<Vec<_>>();
+                players.sort_unstable();
+                for player_entity in players {
+                    self.respawn_player(player_entity);
+                }
+
+                for (_entity, stats) in self.world.query_mut::<&mut Stats>() {
+                    *stats = Stats::default();
+                }
+            }
+            MatchPhase::Finished => {
+                self.events.push(GameEvent::MatchFinished {
+                    score: self.score.clone(),
+                    scoreboard: self.scoreboard(),
+                });
+
+                // Freeze everyone in place
+                for (_entity, (velocity, melee)) in
+                    self.world.query_mut::<(&mut Velocity, &mut Melee)>()
+                {
+                    velocity.dx = 0.0;
+                    velocity.dy = 0.0;
+                    melee.active = false;
+                }
+            }
+            MatchPhase::Warmup | MatchPhase::Overtime => {}
+        }
+    }
+
+    // Replace the game with a fresh one, keeping the connected players
+    fn reset_match(&mut self) {
+        let players = self
+            .world
+            .query::<(&Metadata, &Team, &LastProcessedInput, Option<&Disconnected>)>()
+            .into_iter()
+            .map(|(_, (metadata, team, last_input, disconnected))| {
+                (
+                    metadata.id.clone(),
+                    *team,
+                    *last_input,
+                    disconnected.is_some(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Players aren't really rejoining, so keep the events from before the reset
+        let events = self.drain_events();
+        let tick = self.tick;
+        *self = Game::new(self.map.clone());
+        self.tick = tick;
+
+        for (id, team, last_input, disconnected) in players {
+            let entity = self.add_player(id, team);
+            let _ = self.world.insert_one(entity, last_input);
+            if disconnected {
+                let _ = self.world.insert_one(entity, Disconnected);
+            }
+        }
+
+        self.events = events;
+        self.events
+            .push(GameEvent::MatchPhaseChanged { phase: self.phase });
+    }
+
+    fn step_match_system(&mut self, dt: f32) {
+        let red_score = self.score.get(&Team::Red).copied().unwrap_or(0);
+        let blue_score = self.score.get(&Team::Blue).copied().unwrap_or(0);
+
+        match self.phase {
+            MatchPhase::Warmup => {
+                // Only count down once both teams have someone to play against
+                let has_both_teams = [Team::Red, Team::Blue].iter().all(|team| {
+                    self.world
+                        .query::<(&Team, &Metadata)>()
+                        .into_iter()
+                        .any(|(_, (player_team, _))| player_team == team)
+                });
+
+                if !has_both_teams {
+                    self.phase_time_remaining = WARMUP_DURATION;
+                    return;
+                }
+
+                self.phase_time_remaining -= dt;
+                if self.phase_time_remaining <= 0.0 {
+                    self.set_phase(MatchPhase::Live);
+                }
+            }
+            MatchPhase::Live => {
+                self.phase_time_remaining -= dt;
+
+                if red_score.max(blue_score) >= SCORE_LIMIT {
+                    self.set_phase(MatchPhase::Finished);
+                } else if self.phase_time_remaining <= 0.0 {
+                    if red_score == blue_score {
+                        self.set_phase(MatchPhase::Overtime);
+                    } else {
+                        self.set_phase(MatchPhase::Finished);
+                    }
+                }
+            }
+            MatchPhase::Overtime => {
+                self.phase_time_remaining -= dt;
+
+                // Sudden death, the first capture wins
+                if red_score != blue_score || self.phase_time_remaining <= 0.0 {
+                    self.set_phase(MatchPhase::Finished);
+                }
+            }
+            MatchPhase::Finished => {
+                self.phase_time_remaining -= dt;
+
+                if self.phase_time_remaining <= 0.0 {
+                    self.reset_match();
+                }
+            }
         }
     }
 
@@ -91,54 +306,130 @@ impl Game {
     }
 
     fn step_movement_system(&mut self, dt: f32) {
+        let obstacles: Vec<Obstacle> = self
+            .world
+            .query::<&Obstacle>()
+            .into_iter()
+            .map(|(_, obstacle)| *obstacle)
+            .collect();
+
         // Apply velocities to positions
-        for (_entity, (position, velocity)) in self.world.query_mut::<(&mut Position, &Velocity)>()
-        {
-            position.x += velocity.dx * dt;
-            position.y += velocity.dy * dt;
+        for (_entity, (position, velocity, radius, previous)) in self.world.query_mut::<(
+            &mut Position,
+            &Velocity,
+            Option<&Radius>,
+            Option<&mut PreviousPosition>,
+        )>() {
+            if let Some(previous) = previous {
+                previous.position = *position;
+            }
+
+            let radius = radius.map_or(0.0, |radius| radius.value);
+            let dx = velocity.dx * dt;
+            let dy = velocity.dy * dt;
+
+            // Move in steps of at most half the radius so dashes can't tunnel through thin walls
+            let distance = (dx * dx + dy * dy).sqrt();
+            let max_step = (radius * 0.5).max(MIN_SWEEP_STEP);
+            let steps = ((distance / max_step).ceil() as u32).clamp(1, MAX_SWEEP_STEPS);
+
+            for _ in 0..steps {
+                position.x += dx / steps as f32;
+                position.y += dy / steps as f32;
+
+                // Push out of walls, which leaves the player sliding along them
+                for obstacle in &obstacles {
+                    if let Some(resolved) =
+                        collision::push_out_of_obstacle(*position, radius, obstacle)
+                    {
+                        *position = resolved;
+                    }
+                }
+            }
 
             // Boundary checking
-            position.x = position.x.max(0.0).min(GRID_X);
-            position.y = position.y.max(0.0).min(GRID_Y);
+            position.x = position.x.max(0.0).min(self.map.width);
+            position.y = position.y.max(0.0).min(self.map.height);
+        }
+    }
+
+    fn rebuild_broadphase(&mut self) {
+        self.broadphase.clear();
+
+        for (entity, (position, radius, previous)) in self
+            .world
+            .query::<(&Position, &Radius, Option<&PreviousPosition>)>()
+            .into_iter()
+        {
+            let from = previous.map_or(*position, |previous| previous.position);
+            let (min, max) = swept_bounds(from, *position, radius.value);
+            self.broadphase.insert(entity, min, max);
         }
     }
 
+    // Entities whose motion this tick might overlap the given entity's
+    fn nearby_entities(&self, entity: Entity) -> Vec<Entity> {
+        let Some((from, to, radius)) = self.swept_circle(entity) else {
+            return Vec::new();
+        };
+
+        let (min, max) = swept_bounds(from, to, radius);
+        self.broadphase.query(min, max)
+    }
+
     fn step_collision_system(&mut self) -> Vec<Entity> {
         let melee_players: Vec<(Entity, Team)> = self
             .world
             .query::<(&Team, &Melee)>()
             .into_iter()
             .filter(|(_, (_, melee))| melee.active)
-            .map(|(entity, (team, _))| (entity, team.clone()))
-            .collect();
-
-        // Get all entities with a team and a metadata because only players have metadata.
-        let all_players: Vec<(Entity, Team)> = self
-            .world
-            .query::<(&Team, &Metadata)>()
-            .into_iter()
-            .map(|(entity, (team, _))| (entity, team.clone()))
+            .map(|(entity, (team, _))| (entity, *team))
             .collect();
 
-        // Check for melee collisions and collect players to respawn
-        let mut players_to_respawn = Vec::new();
+        // Check for melee collisions and collect players to respawn along with who tagged them
+        let mut players_to_respawn: Vec<(Entity, Entity)> = Vec::new();
 
         for (attacker, attacker_team) in melee_players.iter() {
-            for (victim, victim_team) in all_players.iter() {
+            for victim in self.nearby_entities(*attacker) {
+                // Only players have metadata
+                let victim_team = match self.world.query_one::<(&Team, &Metadata)>(victim) {
+                    Ok(mut query) => match query.get() {
+                        Some((team, _)) => *team,
+                        None => continue,
+                    },
+                    Err(_) => continue,
+                };
+
                 // Don't check collision with self or same team
-                if *attacker == *victim || attacker_team == victim_team {
+                if *attacker == victim || *attacker_team == victim_team {
                     continue;
                 }
 
-                // Check collision
-                if self.entities_collide(*attacker, *victim) {
-                    players_to_respawn.push(*victim);
+                // Check collision, a player can only be tagged once per tick
+                if self.entities_collide(*attacker, victim)
+                    && !players_to_respawn
+                        .iter()
+                        .any(|(_, tagged)| *tagged == victim)
+                {
+                    players_to_respawn.push((*attacker, victim));
                 }
             }
         }
 
         // Process respawns directly
-        for player_entity in &players_to_respawn {
+        for (attacker, player_entity) in &players_to_respawn {
+            self.update_stats(*attacker, |stats| stats.tags += 1);
+            self.update_stats(*player_entity, |stats| stats.times_tagged += 1);
+
+            if let (Some(attacker_id), Some(victim_id)) =
+                (self.player_id(*attacker), self.player_id(*player_entity))
+            {
+                self.events.push(GameEvent::PlayerTagged {
+                    attacker: attacker_id,
+                    victim: victim_id,
+                });
+            }
+
             // Drop flag if held
             self.drop_flag_if_held_by(*player_entity);
 
@@ -150,30 +441,38 @@ impl Game {
         Vec::new()
     }
 
-    fn entities_collide(&self, a: Entity, b: Entity) -> bool {
-        let Ok(mut query_a) = self.world.query_one::<(&Position, &Radius)>(a) else {
-            return false;
-        };
+    fn player_id(&self, entity: Entity) -> Option<String> {
+        self.world
+            .get::<&Metadata>(entity)
+            .ok()
+            .map(|metadata| metadata.id.clone())
+    }
 
-        let Some((pos_a, rad_a)) = query_a.get() else {
+    // Checks for contact anywhere along both entities' motion this tick, so fast
+    // movers can't skip past each other between ticks
+    fn entities_collide(&self, a: Entity, b: Entity) -> bool {
+        let Some((from_a, to_a, rad_a)) = self.swept_circle(a) else {
             return false;
         };
 
-        let Ok(mut query_b) = self.world.query_one::<(&Position, &Radius)>(b) else {
+        let Some((from_b, to_b, rad_b)) = self.swept_circle(b) else {
             return false;
         };
 
-        let Some((pos_b, rad_b)) = query_b.get() else {
-            return false;
-        };
+        collision::swept_circles_collide(from_a, to_a, from_b, to_b, rad_a + rad_b)
+    }
 
-        let dx = pos_a.x - pos_b.x;
-        let dy = pos_a.y - pos_b.y;
+    // Start position, end position and radius of an entity over this tick
+    fn swept_circle(&self, entity: Entity) -> Option<(Position, Position, f32)> {
+        let mut query = self
+            .world
+            .query_one::<(&Position, &Radius, Option<&PreviousPosition>)>(entity)
+            .ok()?;
 
-        let dist_sq = dx * dx + dy * dy;
-        let min_dist = rad_a.value + rad_b.value;
+        let (position, radius, previous) = query.get()?;
+        let from = previous.map_or(*position, |previous| previous.position);
 
-        dist_sq < min_dist * min_dist
+        Some((from, *position, radius.value))
     }
 
     fn drop_flag_if_held_by(&mut self, player_entity: Entity) {
@@ -182,60 +481,57 @@ impl Game {
             Err(_) => return,
         };
 
-        // Check and handle red flag
-        if let Ok(item) = self.world.get::<&Item>(self.red_flag) {
-            if let Some(holder) = &item.held_by {
-                if holder == &player_id {
-                    if let Ok(mut item) = self.world.get::<&mut Item>(self.red_flag) {
-                        item.held_by = None;
-
-                        // Get player position to drop the flag there
-                        if let Ok(player_pos) = self.world.get::<&Position>(player_entity) {
-                            if let Ok(mut flag_pos) = self.world.get::<&mut Position>(self.red_flag)
-                            {
-                                flag_pos.x = player_pos.x;
-                                flag_pos.y = player_pos.y;
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        // Get player position to drop the flag there
+        let drop_position = match self.world.get::<&Position>(player_entity) {
+            Ok(position) => *position,
+            Err(_) => return,
+        };
 
-        // Check and handle blue flag
-        if let Ok(item) = self.world.get::<&Item>(self.blue_flag) {
-            if let Some(holder) = &item.held_by {
-                if holder == &player_id {
-                    if let Ok(mut item) = self.world.get::<&mut Item>(self.blue_flag) {
-                        item.held_by = None;
-
-                        // Get player position to drop the flag there
-                        if let Ok(player_pos) = self.world.get::<&Position>(player_entity) {
-                            if let Ok(mut flag_pos) =
-                                self.world.get::<&mut Position>(self.blue_flag)
-                            {
-                                flag_pos.x = player_pos.x;
-                                flag_pos.y = player_pos.y;
-                            }
-                        }
-                    }
-                }
+        for flag in [self.red_flag, self.blue_flag] {
+            let Ok((item, flag_pos, team, auto_return)) =
+                self.world
+                    .query_one_mut::<(&mut Item, &mut Position, &Team, &mut AutoReturn)>(flag)
+            else {
+                continue;
+            };
+
+            if item.held_by.as_ref() == Some(&player_id) {
+                item.held_by = None;
+                *flag_pos = drop_position;
+                // Goes home on its own unless someone picks it up first
+                auto_return.remaining = Some(auto_return.duration);
+
+                self.events.push(GameEvent::FlagDropped {
+                    team: *team,
+                    player_id: player_id.clone(),
+                });
             }
         }
     }
 
+    // Cycle through the team's spawn points so players don't stack up
+    fn next_spawn_position(&mut self, team: Team) -> Position {
+        let spawn_positions = &self.map.team(team).spawn_positions;
+        let cursor = self.spawn_cursor.entry(team).or_insert(0);
+
+        let position = spawn_positions
+            .get(*cursor % spawn_positions.len().max(1))
+            .copied()
+            .unwrap_or(self.map.team(team).flag_position);
+        *cursor = cursor.wrapping_add(1);
+
+        position
+    }
+
     fn respawn_player(&mut self, player_entity: Entity) {
         // Get player team
         let team = match self.world.get::<&Team>(player_entity) {
-            Ok(team) => team.clone(),
+            Ok(team) => *team,
             Err(_) => return, // Can't respawn if no team
         };
 
         // Get spawn position
-        let spawn_pos = match *team {
-            Team::Red => RED_TEAM.spawn_position,
-            Team::Blue => BLUE_TEAM.spawn_position,
-        };
+        let spawn_pos = self.next_spawn_position(team);
 
         // Update position
         if let Ok(mut pos) = self.world.get::<&mut Position>(player_entity) {
@@ -243,6 +539,11 @@ impl Game {
             pos.y = spawn_pos.y;
         }
 
+        // Teleporting shouldn't sweep across the map
+        if let Ok(mut previous) = self.world.get::<&mut PreviousPosition>(player_entity) {
+            previous.position = spawn_pos;
+        }
+
         // Reset velocity
         if let Ok(mut vel) = self.world.get::<&mut Velocity>(player_entity) {
             vel.dx = 0.0;
@@ -256,123 +557,245 @@ impl Game {
         }
     }
 
-    fn step_flag_system(&mut self) {
-        // Check for flag pickups
-        // This would be implemented here
+    fn flag_of(&self, team: Team) -> Entity {
+        match team {
+            Team::Red => self.red_flag,
+            Team::Blue => self.blue_flag,
+        }
+    }
+
+    // Whether the team's flag is sitting on its base
+    fn flag_at_home(&self, team: Team) -> bool {
+        let Ok(mut query) = self
+            .world
+            .query_one::<(&Item, &Position)>(self.flag_of(team))
+        else {
+            return false;
+        };
 
-        let player_entities = self.player_map.values().collect::<Vec<_>>();
+        let Some((item, flag_pos)) = query.get() else {
+            return false;
+        };
 
-        for player_entity in player_entities {
-            // Get player team
-            let player_team = match self.world.get::<&Team>(*player_entity) {
-                Ok(team) => team,
-                Err(_) => continue, // Skip if no team
-            };
+        let home = self.map.team(team).flag_position;
+        let dx = flag_pos.x - home.x;
+        let dy = flag_pos.y - home.y;
 
-            // Check for red flag capture (only by blue team)
-            if *player_team == Team::Blue && self.entities_collide(*player_entity, self.red_flag) {
-                // Update flag position to follow the player
-                if let (Ok(player_pos), Ok(mut flag_pos)) = (
-                    self.world.get::<&Position>(*player_entity),
-                    self.world.get::<&mut Position>(self.red_flag),
-                ) {
-                    flag_pos.x = player_pos.x;
-                    flag_pos.y = player_pos.y;
-                }
+        item.held_by.is_none() && dx * dx + dy * dy < f32::EPSILON
+    }
+
+    fn flag_holder(&self, team: Team) -> Option<String> {
+        self.world
+            .get::<&Item>(self.flag_of(team))
+            .ok()
+            .and_then(|item| item.held_by.clone())
+    }
+
+    fn return_flag(&mut self, team: Team) {
+        let home = self.map.team(team).flag_position;
+
+        if let Ok((item, flag_pos, auto_return)) =
+            self.world
+                .query_one_mut::<(&mut Item, &mut Position, &mut AutoReturn)>(self.flag_of(team))
+        {
+            item.held_by = None;
+            flag_pos.x = home.x;
+            flag_pos.y = home.y;
+            auto_return.remaining = None;
+        }
+    }
+
+    // Whether the entity is standing inside the team's capture zone
+    fn in_capture_zone(&self, entity: Entity, team: Team) -> bool {
+        let Ok(pos) = self.world.get::<&Position>(entity) else {
+            return false;
+        };
 
-                // Did blue team bring the flag to the base?
-                // TODO: Make it blue flag spawn instead of blue flag position
-                if self.entities_collide(*player_entity, self.blue_flag) {
-                    self.score.entry(Team::Blue).and_modify(|count| *count += 1);
+        let config = self.map.team(team);
+        let dx = pos.x - config.flag_position.x;
+        let dy = pos.y - config.flag_position.y;
 
-                    // Reset red flag position
-                    let mut red_flag = self.world.get::<&mut Position>(self.red_flag).unwrap();
-                    red_flag.x = RED_TEAM.flag_position.x;
-                    red_flag.y = RED_TEAM.flag_position.y;
+        dx * dx + dy * dy < config.capture_radius * config.capture_radius
+    }
+
+    fn step_auto_return_system(&mut self, dt: f32) {
+        let mut flags_to_return = Vec::new();
+
+        // Count down dropped flags
+        for (_entity, (team, auto_return)) in self.world.query_mut::<(&Team, &mut AutoReturn)>() {
+            if let Some(remaining) = auto_return.remaining.as_mut() {
+                *remaining -= dt;
+
+                if *remaining <= 0.0 {
+                    flags_to_return.push(*team);
                 }
             }
+        }
 
-            // Check for blue flag capture (only by red team)
-            if *player_team == Team::Red && self.entities_collide(*player_entity, self.blue_flag) {
-                // Update flag position to follow the player
-                if let (Ok(player_pos), Ok(mut flag_pos)) = (
-                    self.world.get::<&Position>(*player_entity),
-                    self.world.get::<&mut Position>(self.blue_flag),
-                ) {
-                    flag_pos.x = player_pos.x;
-                    flag_pos.y = player_pos.y;
-                }
+        for team in flags_to_return {
+            self.return_flag(team);
+            self.events.push(GameEvent::FlagReturned {
+                team,
+                player_id: None,
+            });
+        }
+    }
 
-                // Did blue team bring the flag to the base?
-                // TODO: Make it blue flag spawn instead of blue flag position
-                if self.entities_collide(*player_entity, self.red_flag) {
-                    self.score.entry(Team::Red).and_modify(|count| *count += 1);
+    fn step_flag_system(&mut self, dt: f32) {
+        // Check for flag pickups and returns
+        for flag_team in [Team::Red, Team::Blue] {
+            let flag = self.flag_of(flag_team);
+
+            for player_entity in self.nearby_entities(flag) {
+                let (player_team, player_id) =
+                    match self
+                        .world
+                        .query_one::<(&Team, &Metadata, Option<&Disconnected>)>(player_entity)
+                    {
+                        Ok(mut query) => match query.get() {
+                            Some((team, metadata, None)) => (*team, metadata.id.clone()),
+                            // Frozen players would pick a flag straight back up where they dropped it
+                            Some((_, _, Some(_))) => continue,
+                            None => continue, // Skip if not a player
+                        },
+                        Err(_) => continue,
+                    };
+
+                if !self.entities_collide(player_entity, flag) {
+                    continue;
+                }
 
-                    // Reset blue flag position
-                    let mut blue_flag = self.world.get::<&mut Position>(self.blue_flag).unwrap();
-                    blue_flag.x = BLUE_TEAM.flag_position.x;
-                    blue_flag.y = BLUE_TEAM.flag_position.y;
+                if player_team == flag_team {
+                    // Touching your own dropped flag returns it to base
+                    if self.flag_holder(flag_team).is_none() && !self.flag_at_home(flag_team) {
+                        self.return_flag(flag_team);
+                        self.update_stats(player_entity, |stats| stats.returns += 1);
+                        self.events.push(GameEvent::FlagReturned {
+                            team: flag_team,
+                            player_id: Some(player_id),
+                        });
+                    }
+                } else if self.flag_holder(flag_team).is_none() {
+                    // Only one player can hold a flag at a time
+                    if let Ok((item, auto_return)) = self
+                        .world
+                        .query_one_mut::<(&mut Item, &mut AutoReturn)>(flag)
+                    {
+                        item.held_by = Some(player_id.clone());
+                        auto_return.remaining = None;
+
+                        self.update_stats(player_entity, |stats| stats.flag_pickups += 1);
+                        self.events.push(GameEvent::FlagPickedUp {
+                            team: flag_team,
+                            player_id,
+                        });
+                    }
                 }
             }
         }
 
-        // Check for flag captures
-        // This would be implemented here
+        // Move held flags with their carriers and check for captures
+        for flag_team in [Team::Red, Team::Blue] {
+            let Some(holder) = self.flag_holder(flag_team) else {
+                continue;
+            };
+
+            let Some(&carrier) = self.player_map.get(&holder) else {
+                continue;
+            };
 
-        // Check for flag returns
-        // This would be implemented here
+            // Update flag position to follow the carrier
+            if let (Ok(player_pos), Ok(mut flag_pos)) = (
+                self.world.get::<&Position>(carrier),
+                self.world.get::<&mut Position>(self.flag_of(flag_team)),
+            ) {
+                flag_pos.x = player_pos.x;
+                flag_pos.y = player_pos.y;
+            }
+
+            self.update_stats(carrier, |stats| stats.carry_time += dt);
+
+            // Only score by carrying the enemy flag into your own base while your flag is home
+            let scoring_team = flag_team.opponent();
+            if self.in_capture_zone(carrier, scoring_team) && self.flag_at_home(scoring_team) {
+                self.score
+                    .entry(scoring_team)
+                    .and_modify(|count| *count += 1);
+
+                self.return_flag(flag_team);
+                self.update_stats(carrier, |stats| stats.captures += 1);
+                self.events.push(GameEvent::FlagCaptured {
+                    team: flag_team,
+                    player_id: holder,
+                });
+            }
+        }
     }
 
     pub fn make_snapshot(&self) -> Snapshot {
         let players = self
             .world
-            .query::<(&Metadata, &Position, &Team, &Velocity, &Melee)>()
+            .query::<(
+                &Metadata,
+                &Position,
+                &Team,
+                &Velocity,
+                &Melee,
+                &Stats,
+                &LastProcessedInput,
+                Option<&Disconnected>,
+            )>()
             .into_iter()
-            .map(|(_, (metadata, position, team, velocity, melee))| Player {
-                metadata: metadata.clone(),
-                position: position.clone(),
-                velocity: velocity.clone(),
-                team: team.clone(),
-                melee_active: melee.active,
-            })
+            .map(
+                |(
+                    _,
+                    (metadata, position, team, velocity, melee, stats, last_input, disconnected),
+                )| Player {
+                    metadata: metadata.clone(),
+                    position: *position,
+                    velocity: *velocity,
+                    team: *team,
+                    melee_active: melee.active,
+                    stats: *stats,
+                    connected: disconnected.is_none(),
+                    last_processed_input: last_input.sequence,
+                    last_processed_tick: last_input.client_tick,
+                },
+            )
             .collect();
 
         let flags = self
             .world
-            .query::<(&Item, &Position, &Team)>()
+            .query::<(&Item, &Position, &Team, &AutoReturn)>()
             .into_iter()
-            .map(|(_, (item, position, team))| Flag {
-                position: position.clone(),
-                team: team.clone(),
+            .map(|(_, (item, position, team, auto_return))| Flag {
+                position: *position,
+                team: *team,
                 item: item.clone(),
+                auto_return_remaining: auto_return.remaining,
             })
             .collect();
 
         Snapshot {
+            sequence: self.tick,
             players,
             flags,
             score: self.score.clone(),
+            phase: self.phase,
+            time_remaining: self.phase_time_remaining.max(0.0),
         }
     }
 
-    pub fn add_player(&mut self, id: String, team: Team) -> Entity {
-        self.remove_player(&id); // Deduplicate players
-
-        let start_x = match team {
-            Team::Red => RED_TEAM.spawn_position.x,
-            Team::Blue => BLUE_TEAM.spawn_position.x,
-        };
-
-        let start_y = match team {
-            Team::Red => RED_TEAM.spawn_position.y,
-            Team::Blue => BLUE_TEAM.spawn_position.y,
-        };
+    // Callers make sure the id is free, reusing one would orphan the old entity.
+    // Go through Input::CreatePlayer from outside, which checks.
+    fn add_player(&mut self, id: String, team: Team) -> Entity {
+        let start_position = self.next_spawn_position(team);
 
         let player_entity = self.world.spawn((
             Metadata { id: id.clone() },
-            Position {
-                x: start_x,
-                y: start_y,
+            start_position,
+            PreviousPosition {
+                position: start_position,
             },
             Radius { value: 5.0 },
             Velocity { dx: 0.0, dy: 0.0 },
@@ -382,37 +805,133 @@ impl Game {
                 cooldown: 0.0,
                 max_cooldown: MELEE_COOLDOWN,
             },
+            Stats::default(),
+            LastProcessedInput {
+                sequence: 0,
+                client_tick: 0,
+            },
         ));
 
-        self.player_map.insert(id, player_entity);
+        self.player_map.insert(id.clone(), player_entity);
+        self.events.push(GameEvent::PlayerJoined { id, team });
         player_entity
     }
 
     pub fn remove_player(&mut self, id: &str) {
-        if let Some(entity) = self.player_map.remove(id) {
+        if let Some(entity) = self.player_map.get(id).copied() {
+            self.drop_flag_if_held_by(entity);
+            self.player_map.remove(id);
             let _ = self.world.despawn(entity);
+
+            self.events
+                .push(GameEvent::PlayerLeft { id: id.to_string() });
         }
     }
 
-    pub fn get_player(&self, id: String) -> &Entity {
-        self.player_map.get(&id).unwrap()
+    // Move a player to the other side, used to keep teams balanced
+    pub fn change_team(&mut self, id: &str, team: Team) {
+        let Some(entity) = self.player_map.get(id).copied() else {
+            return;
+        };
+
+        match self.world.get::<&Team>(entity) {
+            Ok(current) if *current != team => {}
+            _ => return,
+        }
+
+        self.drop_flag_if_held_by(entity);
+
+        if let Ok(mut current) = self.world.get::<&mut Team>(entity) {
+            *current = team;
+        }
+
+        self.respawn_player(entity);
+        self.events.push(GameEvent::PlayerChangedTeam {
+            id: id.to_string(),
+            team,
+        });
+    }
+
+    pub fn get_player(&self, id: &str) -> Result<Entity, GameError> {
+        self.player_map
+            .get(id)
+            .copied()
+            .ok_or_else(|| GameError::UnknownPlayer(id.to_string()))
     }
 
     // Set player's movement intent
-    pub fn apply_input(&mut self, input: Input) -> Result<(), ComponentError> {
+    pub fn apply_input(&mut self, input: Input) -> Result<(), GameError> {
+        // Count client inputs as processed even when the phase ignores them,
+        // otherwise the client would keep replaying them
+        if let Input::PlayerMove {
+            player_id,
+            sequence,
+            client_tick,
+            ..
+        }
+        | Input::PlayerMelee {
+            player_id,
+            sequence,
+            client_tick,
+        } = &input
+        {
+            let entity = self.get_player(player_id)?;
+            let mut last_input = self.world.get::<&mut LastProcessedInput>(entity)?;
+            if *sequence > last_input.sequence {
+                last_input.sequence = *sequence;
+                last_input.client_tick = *client_tick;
+            }
+        }
+
         match input {
             Input::CreatePlayer { team, id } => {
+                // Replacing a player is the server's call, never do it silently
+                if self.player_map.contains_key(&id) {
+                    return Err(GameError::DuplicatePlayer(id));
+                }
                 self.add_player(id, team);
             }
-            Input::RemovePlayer { id } => self.remove_player(&id),
+            Input::RemovePlayer { id } => {
+                self.get_player(&id)?;
+                self.remove_player(&id);
+            }
+            Input::ChangeTeam { id, team } => {
+                self.get_player(&id)?;
+                self.change_team(&id, team);
+            }
+            Input::PlayerDisconnected { id } => {
+                let entity = self.get_player(&id)?;
+                *self.world.get::<&mut Velocity>(entity)? = Velocity { dx: 0.0, dy: 0.0 };
+                // A frozen carrier would keep the other team from ever scoring
+                self.drop_flag_if_held_by(entity);
+                let _ = self.world.insert_one(entity, Disconnected);
+                self.events.push(GameEvent::PlayerDisconnected { id });
+            }
+            Input::PlayerReconnected { id } => {
+                let entity = self.get_player(&id)?;
+                if self.world.remove_one::<Disconnected>(entity).is_ok() {
+                    self.events.push(GameEvent::PlayerReconnected { id });
+                }
+            }
+            Input::PlayerMove { .. } | Input::PlayerMelee { .. }
+                if self.phase == MatchPhase::Finished =>
+            {
+                // Movement is frozen after game over
+            }
+            Input::PlayerMelee { .. } if self.phase == MatchPhase::Warmup => {
+                // No melee during warmup
+            }
             Input::PlayerMove {
                 velocity,
                 player_id,
+                ..
             } => {
-                let entity = self.get_player(player_id);
-                let melee = self.world.get::<&Melee>(*entity)?;
+                let entity = self.get_player(&player_id)?;
+                let team = *self.world.get::<&Team>(entity)?;
+                let velocity = velocity.clamped(self.map.team(team).max_speed)?;
+                let melee = self.world.get::<&Melee>(entity)?;
 
-                let mut player_velocity = self.world.get::<&mut Velocity>(*entity)?;
+                let mut player_velocity = self.world.get::<&mut Velocity>(entity)?;
                 if melee.active {
                 } else if melee.cooldown > 0. {
                     player_velocity.dx = velocity.dx * MELEE_COOLDOWN_SPEED_MULTIPLIER;
@@ -422,25 +941,25 @@ impl Game {
                     player_velocity.dy = velocity.dy;
                 }
             }
-            Input::PlayerMelee { player_id } => {
-                let player = self.get_player(player_id);
+            Input::PlayerMelee { player_id, .. } => {
+                let player = self.get_player(&player_id)?;
 
                 // Get the velocity values first without keeping the borrow
                 let (dx, dy) = {
-                    let velocity = self.world.get::<&Velocity>(*player)?;
+                    let velocity = self.world.get::<&Velocity>(player)?;
                     (velocity.dx, velocity.dy)
                 }; // Borrow is dropped here
 
                 // Check if the player is moving
                 if dx != 0.0 || dy != 0.0 {
-                    let mut melee = self.world.get::<&mut Melee>(*player)?;
+                    let mut melee = self.world.get::<&mut Melee>(player)?;
 
                     if melee.cooldown <= 0.0 && !melee.active {
                         melee.active = true;
                         melee.cooldown = melee.max_cooldown;
 
                         // Now we can borrow velocity mutably since the immutable borrow is gone
-                        let mut player_velocity = self.world.get::<&mut Velocity>(*player)?;
+                        let mut player_velocity = self.world.get::<&mut Velocity>(player)?;
 
                         let length = (dx * dx + dy * dy).sqrt();
                         if length > 0.0 {
@@ -455,16 +974,45 @@ impl Game {
         Ok(())
     }
 
-    // Update the game state based on the delta time (frame-independently)
-    pub fn step(&mut self, dt: f32) {
-        // Run each system in order
-        self.step_melee_system(dt);
-        self.step_movement_system(dt);
+    // Advance one fixed tick, use FixedTimestep to drive this from a wall clock.
+    // The same inputs applied between the same ticks always give the same world_hash.
+    pub fn step(&mut self) {
+        let dt = TICK_RATE;
+        self.tick += 1;
+
+        // Nothing moves after game over, just wait for the next round
+        if self.phase != MatchPhase::Finished {
+            // Run each system in order
+            self.step_melee_system(dt);
+            self.step_movement_system(dt);
+
+            // Run collision system which now handles respawns internally
+            self.rebuild_broadphase();
+            self.step_collision_system();
+        }
 
-        // Run collision system which now handles respawns internally
-        self.step_collision_system();
+        // Flags can only be captured while the match is being played
+        if matches!(self.phase, MatchPhase::Live | MatchPhase::Overtime) {
+            // Respawns moved players around, so refresh the grid first
+            self.rebuild_broadphase();
+            self.step_flag_system(dt);
+            self.step_auto_return_system(dt);
+        }
 
-        // Handle flag logic
-        self.step_flag_system();
+        self.step_match_system(dt);
     }
 }
+
+// Bounding box of a circle moving from one position to another
+fn swept_bounds(from: Position, to: Position, radius: f32) -> (Position, Position) {
+    let min = Position {
+        x: from.x.min(to.x) - radius,
+        y: from.y.min(to.y) - radius,
+    };
+    let max = Position {
+        x: from.x.max(to.x) + radius,
+        y: from.y.max(to.y) + radius,
+    };
+
+    (min, max)
+}
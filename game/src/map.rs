@@ -0,0 +1,125 @@
+// Map loading, arenas are described in JSON so they can change without a client release
+
+use std::{fmt, fs, path::Path};
+
+use crate::{MapConfig, Obstacle, Position, Team, TeamBase};
+
+const DEFAULT_MAP: &str = include_str!("../maps/default.json");
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "failed to read map: {}", e),
+            MapError::Parse(e) => write!(f, "failed to parse map: {}", e),
+            MapError::Invalid(reason) => write!(f, "invalid map: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl MapConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MapError> {
+        let json = fs::read_to_string(path).map_err(MapError::Io)?;
+        Self::from_json(&json)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, MapError> {
+        let map: MapConfig = serde_json::from_str(json).map_err(MapError::Parse)?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    pub fn team(&self, team: Team) -> &TeamBase {
+        match team {
+            Team::Red => &self.red,
+            Team::Blue => &self.blue,
+        }
+    }
+
+    pub fn contains(&self, position: &Position) -> bool {
+        (0.0..=self.width).contains(&position.x) && (0.0..=self.height).contains(&position.y)
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), MapError> {
+        // Written so NaN fails too, maps can come from JS without going through JSON
+        let positive = |value: f32| value.is_finite() && value > 0.0;
+
+        if !(positive(self.width) && positive(self.height)) {
+            return Err(MapError::Invalid(format!(
+                "bounds must be positive, got {}x{}",
+                self.width, self.height
+            )));
+        }
+
+        for team in [Team::Red, Team::Blue] {
+            let base = self.team(team);
+
+            if !positive(base.max_speed) {
+                return Err(MapError::Invalid(format!(
+                    "{:?} max speed must be positive, got {}",
+                    team, base.max_speed
+                )));
+            }
+
+            // Nobody could ever capture into an empty zone
+            if !positive(base.capture_radius) {
+                return Err(MapError::Invalid(format!(
+                    "{:?} capture radius must be positive, got {}",
+                    team, base.capture_radius
+                )));
+            }
+
+            // A flag that returns the moment it drops can never be carried off
+            if !positive(base.flag_return_seconds) {
+                return Err(MapError::Invalid(format!(
+                    "{:?} flag return time must be positive, got {}",
+                    team, base.flag_return_seconds
+                )));
+            }
+
+            if base.spawn_positions.is_empty() {
+                return Err(MapError::Invalid(format!("{:?} has no spawn points", team)));
+            }
+
+            let positions = std::iter::once(&base.flag_position).chain(&base.spawn_positions);
+            for position in positions {
+                if !self.contains(position) {
+                    return Err(MapError::Invalid(format!(
+                        "{:?} base position {:?} is out of bounds",
+                        team, position
+                    )));
+                }
+            }
+        }
+
+        for obstacle in &self.obstacles {
+            let valid = match obstacle {
+                Obstacle::Rect { min, max } => min.x < max.x && min.y < max.y,
+                Obstacle::Circle { radius, .. } => *radius > 0.0,
+            };
+
+            if !valid {
+                return Err(MapError::Invalid(format!(
+                    "obstacle {:?} has no area",
+                    obstacle
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for MapConfig {
+    fn default() -> Self {
+        Self::from_json(DEFAULT_MAP).expect("bundled default map is valid")
+    }
+}
@@ -12,7 +12,7 @@ pub struct Radius {
     pub value: f32,
 }
 
-#[derive(TS, Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(TS, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[ts(export)]
 pub struct Velocity {
     pub dx: f32,
@@ -20,7 +20,7 @@ pub struct Velocity {
 }
 
 // Position component
-#[derive(TS, Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(TS, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[ts(export)]
 pub struct Position {
     pub x: f32,
@@ -41,6 +41,9 @@ pub const MELEE_DURATION: f32 = 0.2;
 pub const MELEE_SPEED_MULTIPLIER: f32 = 90.0; // How much faster the player moves during attack
 pub const MELEE_COOLDOWN_SPEED_MULTIPLIER: f32 = 0.5;
 
+// Fastest a player can move on their own, maps can override it per team
+pub const MAX_PLAYER_SPEED: f32 = 40.0;
+
 // Team component
 #[derive(TS, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
 #[ts(export)]
@@ -50,20 +53,129 @@ pub enum Team {
     Blue,
 }
 
+impl Team {
+    pub fn opponent(&self) -> Team {
+        match self {
+            Team::Red => Team::Blue,
+            Team::Blue => Team::Red,
+        }
+    }
+}
+
 // Player component
-#[derive(TS, Debug, Clone, Serialize, Deserialize)]
+#[derive(TS, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[ts(export)]
 pub struct Metadata {
     pub id: String,
 }
 
 // Flag component
-#[derive(TS, Debug, Clone, Serialize, Deserialize)]
+#[derive(TS, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[ts(export)]
 pub struct Item {
     pub held_by: Option<String>,
 }
 
+// Per-player match stats
+#[derive(TS, Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[ts(export)]
+pub struct Stats {
+    pub tags: u32,
+    pub times_tagged: u32,
+    pub flag_pickups: u32,
+    pub captures: u32,
+    pub returns: u32,
+    // Seconds spent carrying the enemy flag
+    pub carry_time: f32,
+}
+
+// Flag auto-return countdown, only ticking while the flag is dropped
+#[derive(TS, Debug, Clone, Copy, Serialize, Deserialize)]
+#[ts(export)]
+pub struct AutoReturn {
+    pub remaining: Option<f32>,
+    pub duration: f32,
+}
+
+// Used when the map doesn't set flag_return_seconds
+pub const FLAG_AUTO_RETURN_DURATION: f32 = 10.0;
+
+// A team's half of the map
+#[derive(TS, Debug, Clone, Serialize, Deserialize)]
+#[ts(export)]
+pub struct TeamBase {
+    pub flag_position: Position,
+    pub spawn_positions: Vec<Position>,
+    // Radius of the capture zone around the flag base
+    pub capture_radius: f32,
+    #[serde(default = "default_max_speed")]
+    pub max_speed: f32,
+    // How long the team's flag lies dropped before it goes home on its own
+    #[serde(default = "default_flag_return_seconds")]
+    pub flag_return_seconds: f32,
+}
+
+fn default_max_speed() -> f32 {
+    MAX_PLAYER_SPEED
+}
+
+fn default_flag_return_seconds() -> f32 {
+    FLAG_AUTO_RETURN_DURATION
+}
+
+// Named axis-aligned region of the map
+#[derive(TS, Debug, Clone, Serialize, Deserialize)]
+#[ts(export)]
+pub struct Zone {
+    pub name: String,
+    pub min: Position,
+    pub max: Position,
+}
+
+// Static wall the players collide with
+#[derive(TS, Debug, Clone, Copy, Serialize, Deserialize)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub enum Obstacle {
+    Rect { min: Position, max: Position },
+    Circle { center: Position, radius: f32 },
+}
+
+// Everything needed to build and render an arena
+#[derive(TS, Debug, Clone, Serialize, Deserialize)]
+#[ts(export)]
+pub struct MapConfig {
+    pub name: String,
+    pub width: f32,
+    pub height: f32,
+    pub red: TeamBase,
+    pub blue: TeamBase,
+    pub zones: Vec<Zone>,
+    #[serde(default)]
+    pub obstacles: Vec<Obstacle>,
+}
+
+// Match lifecycle
+#[derive(TS, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchPhase {
+    Warmup,
+    Live,
+    Overtime,
+    Finished,
+}
+
+// Every step advances the simulation by exactly one tick of this length
+pub const TICK_MILLIS: u64 = 20;
+pub const TICK_RATE: f32 = TICK_MILLIS as f32 / 1000.0;
+
+pub const WARMUP_DURATION: f32 = 10.0;
+pub const MATCH_DURATION: f32 = 300.0;
+pub const OVERTIME_DURATION: f32 = 60.0;
+pub const GAME_OVER_DURATION: f32 = 10.0; // How long the results stay up before the next round
+pub const SCORE_LIMIT: u32 = 3;
+
 #[derive(Debug, Serialize, Deserialize, Clone, TS)]
 #[ts(export)]
 #[serde(rename_all = "camelCase")]
@@ -73,10 +185,14 @@ pub enum Input {
         id: String,
         team: Team,
     },
+    // Sequence and client_tick are the client's, reported back as the player's
+    // last_processed_input and last_processed_tick
     #[serde(rename_all = "camelCase")]
     PlayerMove {
         player_id: String,
         velocity: Velocity,
+        sequence: u32,
+        client_tick: u64,
     },
     #[serde(rename_all = "camelCase")]
     RemovePlayer {
@@ -84,10 +200,24 @@ pub enum Input {
     },
     PlayerMelee {
         player_id: String,
+        sequence: u32,
+        client_tick: u64,
+    },
+    // Sent by the server when rebalancing teams
+    ChangeTeam {
+        id: String,
+        team: Team,
+    },
+    // Sent by the server when a socket drops, the player is frozen until they come back
+    PlayerDisconnected {
+        id: String,
+    },
+    PlayerReconnected {
+        id: String,
     },
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, TS)]
 #[ts(export)]
 pub struct Player {
     pub metadata: Metadata,
@@ -95,21 +225,222 @@ pub struct Player {
     pub velocity: Velocity,
     pub team: Team,
     pub melee_active: bool,
+    pub stats: Stats,
+    // False while the server is holding the player for a reconnect
+    pub connected: bool,
+    // Sequence of the newest client input the server has applied, anything after it
+    // still needs to be replayed on top of this snapshot when predicting
+    pub last_processed_input: u32,
+    // Client tick that input was sent on, lines the client's prediction up with server ticks
+    pub last_processed_tick: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, TS)]
 #[ts(export)]
+pub struct ScoreboardEntry {
+    pub id: String,
+    pub team: Team,
+    pub stats: Stats,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, TS)]
+#[ts(export)]
 pub struct Flag {
     pub position: Position,
     pub team: Team,
     pub item: Item,
+    pub auto_return_remaining: Option<f32>,
 }
 
 // All the data that needs to be sent to the client to render the game
-#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, TS)]
 #[ts(export)]
 pub struct Snapshot {
+    // Tick the snapshot was taken on, clients ack this for deltas
+    pub sequence: u64,
     pub players: Vec<Player>,
     pub flags: Vec<Flag>,
     pub score: HashMap<Team, u32>,
+    pub phase: MatchPhase,
+    pub time_remaining: f32,
+}
+
+// Fields of a player that changed since the baseline, None where they didn't.
+// A walking player usually only needs its position.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, TS)]
+#[ts(export)]
+pub struct PlayerDelta {
+    pub id: String,
+    pub position: Option<Position>,
+    pub velocity: Option<Velocity>,
+    pub team: Option<Team>,
+    pub melee_active: Option<bool>,
+    pub stats: Option<Stats>,
+    pub connected: Option<bool>,
+    pub last_processed_input: Option<u32>,
+    pub last_processed_tick: Option<u64>,
+}
+
+// Changes between an acked baseline snapshot and a newer one.
+// Fields left as None are unchanged from the baseline.
+#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct SnapshotDelta {
+    pub sequence: u64,
+    pub baseline: u64,
+    // Players the baseline doesn't have, in full
+    pub added_players: Vec<Player>,
+    pub changed_players: Vec<PlayerDelta>,
+    pub removed_players: Vec<String>,
+    // Changed flags
+    pub flags: Vec<Flag>,
+    pub score: Option<HashMap<Team, u32>>,
+    pub phase: Option<MatchPhase>,
+    pub time_remaining: f32,
+}
+
+// Things that happened during a tick, for kill-feeds, sounds and announcers.
+// `team` on flag events is always the team that owns the flag.
+#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub enum GameEvent {
+    PlayerTagged {
+        attacker: String,
+        victim: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    FlagPickedUp {
+        team: Team,
+        player_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    FlagDropped {
+        team: Team,
+        player_id: String,
+    },
+    // No player_id when the flag returned on its own
+    #[serde(rename_all = "camelCase")]
+    FlagReturned {
+        team: Team,
+        player_id: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    FlagCaptured {
+        team: Team,
+        player_id: String,
+    },
+    PlayerJoined {
+        id: String,
+        team: Team,
+    },
+    PlayerLeft {
+        id: String,
+    },
+    PlayerDisconnected {
+        id: String,
+    },
+    PlayerReconnected {
+        id: String,
+    },
+    PlayerChangedTeam {
+        id: String,
+        team: Team,
+    },
+    MatchPhaseChanged {
+        phase: MatchPhase,
+    },
+    // Final results, sent when the match ends
+    MatchFinished {
+        score: HashMap<Team, u32>,
+        scoreboard: Vec<ScoreboardEntry>,
+    },
+}
+
+// Bumped whenever a change to these types would break older clients
+pub const PROTOCOL_VERSION: u32 = 7;
+
+// Websocket close codes sent when the handshake fails
+pub const CLOSE_BAD_HANDSHAKE: u16 = 4000;
+pub const CLOSE_PROTOCOL_MISMATCH: u16 = 4001;
+// Sent to the old connection when the same session connects again
+pub const CLOSE_SESSION_REPLACED: u16 = 4002;
+// Someone is already playing as the requested id
+pub const CLOSE_ID_IN_USE: u16 = 4003;
+// The room already has as many players as it takes
+pub const CLOSE_ROOM_FULL: u16 = 4004;
+
+// Server's reply to a hello it can speak to
+#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct Welcome {
+    pub protocol_version: u32,
+    pub server_build: String,
+    pub player_id: String,
+    // Pass back as `session` when reconnecting to keep the same player
+    pub session_token: String,
+    // Seconds between snapshots
+    pub tick_rate: f32,
+    pub map: MapConfig,
+}
+
+// Everything the server sends over the websocket
+#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub enum GameMessage {
+    // Sent once after the handshake
+    Welcome(Welcome),
+    Snapshot(Snapshot),
+    Delta(SnapshotDelta),
+    Events(Vec<GameEvent>),
+}
+
+// How messages are encoded on the wire, picked by the client when connecting
+#[derive(TS, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub enum WireFormat {
+    // Structs as maps with field names, easy to read when debugging
+    #[default]
+    Named,
+    // Structs as positional arrays, a fraction of the size
+    Compact,
+}
+
+// Input as sent by a client, the server stamps the connection's player id
+#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub enum ClientInput {
+    Move { velocity: Velocity },
+    Melee,
+}
+
+// A client input numbered so the client can tell which ones a snapshot already includes
+#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[ts(export)]
+pub struct InputEnvelope {
+    // Goes up by one for every input the client sends, starting from 1
+    pub sequence: u32,
+    // Snapshot sequence the client was showing when it sampled the input
+    pub client_tick: u64,
+    pub input: ClientInput,
+}
+
+// Everything the client sends over the websocket
+#[derive(Debug, Serialize, Deserialize, Clone, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub enum ClientMessage {
+    // Must be the first message on a new connection
+    #[serde(rename_all = "camelCase")]
+    Hello {
+        protocol_version: u32,
+    },
+    Input(InputEnvelope),
+    // Latest snapshot sequence the client has, used as the next delta baseline
+    Ack {
+        sequence: u64,
+    },
 }
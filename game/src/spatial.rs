@@ -0,0 +1,68 @@
+// Uniform grid broadphase so collision systems only test nearby entities
+
+use std::collections::HashMap;
+
+use hecs::Entity;
+
+use crate::Position;
+
+pub(crate) struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<Entity>>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    // Empty every cell but keep the allocations for the next tick
+    pub fn clear(&mut self) {
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+
+    // Add an entity to every cell its bounding box touches
+    pub fn insert(&mut self, entity: Entity, min: Position, max: Position) {
+        let (min_cell, max_cell) = self.cell_range(min, max);
+
+        for x in min_cell.0..=max_cell.0 {
+            for y in min_cell.1..=max_cell.1 {
+                self.cells.entry((x, y)).or_default().push(entity);
+            }
+        }
+    }
+
+    // Entities sharing a cell with the bounding box, sorted so callers iterate deterministically
+    pub fn query(&self, min: Position, max: Position) -> Vec<Entity> {
+        let (min_cell, max_cell) = self.cell_range(min, max);
+        let mut found = Vec::new();
+
+        for x in min_cell.0..=max_cell.0 {
+            for y in min_cell.1..=max_cell.1 {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    found.extend_from_slice(cell);
+                }
+            }
+        }
+
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    fn cell_range(&self, min: Position, max: Position) -> ((i32, i32), (i32, i32)) {
+        let cell = |position: Position| {
+            (
+                (position.x / self.cell_size).floor() as i32,
+                (position.y / self.cell_size).floor() as i32,
+            )
+        };
+
+        (cell(min), cell(max))
+    }
+}
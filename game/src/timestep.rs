@@ -0,0 +1,47 @@
+// Fixed-timestep driver, turns however much wall time passed into a whole number of ticks
+// so the simulation itself never sees an uneven dt
+
+use std::time::Duration;
+
+use crate::TICK_MILLIS;
+
+// A caller that stalled catches up at most this many ticks at once, the rest is dropped
+pub const MAX_CATCH_UP_TICKS: u32 = 5;
+
+pub struct FixedTimestep {
+    tick: Duration,
+    // Wall time not yet spent on a tick
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    pub fn new(tick: Duration) -> Self {
+        Self {
+            tick,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    // Bank the elapsed time and return how many ticks are due
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator += elapsed;
+
+        let tick = self.tick.as_nanos();
+        let banked = self.accumulator.as_nanos();
+        let due = (banked / tick).min(MAX_CATCH_UP_TICKS as u128) as u32;
+
+        // Keep the partial tick, anything beyond the catch-up limit is gone
+        self.accumulator = if due == MAX_CATCH_UP_TICKS {
+            Duration::from_nanos((banked % tick) as u64)
+        } else {
+            self.accumulator - self.tick * due
+        };
+        due
+    }
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(TICK_MILLIS))
+    }
+}
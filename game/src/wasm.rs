@@ -0,0 +1,44 @@
+// JS bindings for the browser, which runs the same simulation as the server to predict
+// its own player. Values cross the boundary in the same shape as the ts-rs bindings.
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::{Game, Input, MapConfig};
+
+#[wasm_bindgen(js_name = Game)]
+pub struct WasmGame {
+    game: Game,
+}
+
+#[wasm_bindgen(js_class = Game)]
+impl WasmGame {
+    // Takes the MapConfig from the server's welcome
+    #[wasm_bindgen(constructor)]
+    pub fn new(map: JsValue) -> Result<WasmGame, JsValue> {
+        let map: MapConfig = serde_wasm_bindgen::from_value(map)?;
+        map.validate().map_err(JsError::from)?;
+        Ok(Self {
+            game: Game::new(map),
+        })
+    }
+
+    #[wasm_bindgen(js_name = applyInput)]
+    pub fn apply_input(&mut self, input: JsValue) -> Result<(), JsValue> {
+        let input: Input = serde_wasm_bindgen::from_value(input)?;
+        self.game.apply_input(input).map_err(JsError::from)?;
+        Ok(())
+    }
+
+    // One fixed tick, the caller runs as many as its clock says are due
+    pub fn step(&mut self) {
+        self.game.step();
+    }
+
+    #[wasm_bindgen(js_name = makeSnapshot)]
+    pub fn make_snapshot(&self) -> Result<JsValue, JsValue> {
+        // Plain objects and numbers, like the msgpack decoder gives the client
+        let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+        Ok(self.game.make_snapshot().serialize(&serializer)?)
+    }
+}
@@ -0,0 +1,19 @@
+// Wire encoding for everything sent between server and client
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::WireFormat;
+
+impl WireFormat {
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        match self {
+            WireFormat::Named => rmp_serde::to_vec_named(value),
+            WireFormat::Compact => rmp_serde::to_vec(value),
+        }
+    }
+
+    // Structs decode from either maps or arrays, so this accepts both formats
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, rmp_serde::decode::Error> {
+        rmp_serde::from_slice(bytes)
+    }
+}
@@ -0,0 +1,82 @@
+// Fixtures and scripted runs shared by the integration tests and benches.
+// Each binary builds its own copy of this module and only uses some of it.
+#![allow(dead_code)]
+
+use game::{Game, Input, MapConfig, MatchPhase, Position, Snapshot, Team};
+
+pub fn create(game: &mut Game, id: &str, team: Team) {
+    game.apply_input(Input::CreatePlayer {
+        id: id.to_string(),
+        team,
+    })
+    .unwrap();
+}
+
+pub fn teleport(game: &mut Game, id: &str, to: Position) {
+    let entity = game.player_map[id];
+    *game.world.get::<&mut Position>(entity).unwrap() = to;
+}
+
+// A game on the given map with these players that has made it past warmup,
+// flags can only be taken once the match is live
+pub fn live_game_on<S: AsRef<str>>(
+    map: MapConfig,
+    players: impl IntoIterator<Item = (S, Team)>,
+) -> Game {
+    let mut game = Game::new(map);
+    for (id, team) in players {
+        create(&mut game, id.as_ref(), team);
+    }
+
+    while game.phase != MatchPhase::Live {
+        game.step();
+    }
+    game.drain_events();
+    game
+}
+
+pub fn live_game<S: AsRef<str>>(players: impl IntoIterator<Item = (S, Team)>) -> Game {
+    live_game_on(MapConfig::default(), players)
+}
+
+// Inputs applied before the tick they're listed under
+pub type Script = Vec<(u64, Input)>;
+
+// What each build needs to provide to run a script
+pub trait Simulation {
+    fn apply(&mut self, input: Input);
+    fn advance(&mut self);
+    // Determinism only needs hashes
+    fn snapshot(&self) -> Snapshot;
+}
+
+impl Simulation for Game {
+    fn apply(&mut self, input: Input) {
+        // Some inputs are meant to be refused, e.g. moves from a player who already left
+        let _ = self.apply_input(input);
+    }
+
+    fn advance(&mut self) {
+        self.step();
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        self.make_snapshot()
+    }
+}
+
+// Runs the script for the given number of ticks, calling after_tick once each one is done
+pub fn play<S: Simulation>(
+    simulation: &mut S,
+    script: &[(u64, Input)],
+    ticks: u64,
+    mut after_tick: impl FnMut(&S),
+) {
+    for tick in 0..ticks {
+        for (_, input) in script.iter().filter(|(at, _)| *at == tick) {
+            simulation.apply(input.clone());
+        }
+        simulation.advance();
+        after_tick(simulation);
+    }
+}
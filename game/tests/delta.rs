@@ -0,0 +1,163 @@
+// Deltas must rebuild exactly the snapshot they were diffed from, the client's applyDelta
+// in WebsocketManager.tsx mirrors SnapshotDelta::apply
+
+mod common;
+
+use common::{create, teleport};
+use game::{Game, Input, MapConfig, MatchPhase, Snapshot, Team, Velocity, WireFormat};
+
+// Sorted by id so the comparison doesn't depend on entity iteration order
+fn sorted(mut snapshot: Snapshot) -> Snapshot {
+    snapshot
+        .players
+        .sort_by(|a, b| a.metadata.id.cmp(&b.metadata.id));
+    snapshot
+}
+
+// Snapshots from a match that sees joins, leaves, flag changes, a capture and phase changes
+fn snapshots() -> Vec<Snapshot> {
+    let mut game = Game::new(MapConfig::default());
+    let mut snapshots = vec![game.make_snapshot()];
+
+    create(&mut game, "ada", Team::Red);
+    create(&mut game, "bo", Team::Blue);
+    game.step();
+    snapshots.push(game.make_snapshot());
+
+    game.apply_input(Input::PlayerMove {
+        player_id: "ada".to_string(),
+        velocity: Velocity { dx: 30.0, dy: 10.0 },
+        sequence: 1,
+        client_tick: 0,
+    })
+    .unwrap();
+    create(&mut game, "cy", Team::Red);
+    game.step();
+    snapshots.push(game.make_snapshot());
+
+    while game.phase != MatchPhase::Live {
+        game.step();
+    }
+    snapshots.push(game.make_snapshot());
+
+    // Bo takes the red flag home
+    let (red_home, blue_home) = (game.map.red.flag_position, game.map.blue.flag_position);
+    teleport(&mut game, "bo", red_home);
+    game.step();
+    snapshots.push(game.make_snapshot());
+
+    teleport(&mut game, "bo", blue_home);
+    game.step();
+    snapshots.push(game.make_snapshot());
+
+    game.apply_input(Input::RemovePlayer {
+        id: "cy".to_string(),
+    })
+    .unwrap();
+    create(&mut game, "di", Team::Red);
+    game.step();
+    snapshots.push(game.make_snapshot());
+
+    snapshots
+}
+
+#[test]
+fn apply_rebuilds_the_diffed_snapshot() {
+    let snapshots = snapshots();
+
+    for (i, baseline) in snapshots.iter().enumerate() {
+        for current in &snapshots[i..] {
+            let delta = current.diff(baseline);
+            assert_eq!(
+                sorted(delta.apply(baseline)),
+                sorted(current.clone()),
+                "snapshot {} against baseline {}",
+                current.sequence,
+                baseline.sequence
+            );
+        }
+    }
+}
+
+#[test]
+fn delta_only_carries_what_changed() {
+    let snapshots = snapshots();
+    let last = snapshots.last().unwrap();
+
+    // Nothing changed against itself apart from the clock
+    let unchanged = last.diff(last);
+    assert!(unchanged.added_players.is_empty());
+    assert!(unchanged.changed_players.is_empty());
+    assert!(unchanged.removed_players.is_empty());
+    assert!(unchanged.flags.is_empty());
+    assert_eq!(unchanged.score, None);
+    assert_eq!(unchanged.phase, None);
+
+    // From the very start, everyone is new and the capture shows up in the score
+    let from_start = last.diff(&snapshots[0]);
+    let mut ids = from_start
+        .added_players
+        .iter()
+        .map(|player| player.metadata.id.as_str())
+        .collect::<Vec<_>>();
+    ids.sort();
+    assert_eq!(ids, ["ada", "bo", "di"]);
+    assert_eq!(from_start.phase, Some(MatchPhase::Live));
+    assert_eq!(from_start.score.unwrap()[&Team::Blue], 1);
+
+    // Cy left after joining
+    let since_cy = last.diff(&snapshots[2]);
+    assert_eq!(since_cy.removed_players, ["cy"]);
+
+    // The pickup moved the red flag and nothing touched the blue one
+    let pickup = snapshots[4].diff(&snapshots[3]);
+    assert_eq!(pickup.flags.len(), 1);
+    assert_eq!(pickup.flags[0].team, Team::Red);
+    assert_eq!(pickup.flags[0].item.held_by.as_deref(), Some("bo"));
+}
+
+#[test]
+fn walking_players_only_send_their_positions() {
+    let mut game = Game::new(MapConfig::default());
+    for i in 0..20 {
+        let id = format!("player{}", i);
+        let team = if i % 2 == 0 { Team::Red } else { Team::Blue };
+        create(&mut game, &id, team);
+        game.apply_input(Input::PlayerMove {
+            player_id: id,
+            velocity: Velocity {
+                dx: 10.0 + i as f32,
+                dy: -5.0,
+            },
+            sequence: 1,
+            client_tick: 0,
+        })
+        .unwrap();
+    }
+    game.step();
+    let baseline = game.make_snapshot();
+    game.step();
+    let current = game.make_snapshot();
+
+    let delta = current.diff(&baseline);
+    assert_eq!(delta.changed_players.len(), 20);
+    for player in &delta.changed_players {
+        assert!(player.position.is_some());
+        assert_eq!(player.velocity, None);
+        assert_eq!(player.stats, None);
+        assert_eq!(player.last_processed_input, None);
+    }
+
+    // Ids and a marker for every unchanged field still go out, so this is about half
+    for format in [WireFormat::Compact, WireFormat::Named] {
+        let full = format.encode(&current).unwrap().len();
+        let delta = format.encode(&delta).unwrap().len();
+        assert!(
+            delta * 5 < full * 3,
+            "{:?} delta is {} bytes against {} for the snapshot",
+            format,
+            delta,
+            full
+        );
+    }
+}
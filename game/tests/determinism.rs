@@ -0,0 +1,156 @@
+// Replays the same input script on fresh games and checks they agree on the world hash
+// after every tick. A failure names the first tick where the runs split.
+
+mod common;
+
+use std::time::Duration;
+
+use common::Script;
+use game::{FixedTimestep, Game, Input, MAX_CATCH_UP_TICKS, MapConfig, Team, Velocity};
+
+// Long enough to leave warmup and fight for a while in the live match
+const TICKS: u64 = 900;
+
+fn script() -> Script {
+    let mut script = Vec::new();
+    let players = ["ada", "bo", "cy", "di", "ed", "flo", "gus", "hal"];
+
+    for (i, id) in players.iter().enumerate() {
+        let team = if i % 2 == 0 { Team::Red } else { Team::Blue };
+        script.push((
+            i as u64 * 7,
+            Input::CreatePlayer {
+                id: id.to_string(),
+                team,
+            },
+        ));
+    }
+
+    // Everyone charges the other side and swings whenever they can
+    for (i, id) in players.iter().enumerate() {
+        let towards = if i % 2 == 0 { 40.0 } else { -40.0 };
+        for (sequence, tick) in (60..TICKS).step_by(45).enumerate() {
+            script.push((
+                tick + i as u64,
+                Input::PlayerMove {
+                    player_id: id.to_string(),
+                    velocity: Velocity {
+                        dx: towards,
+                        dy: (sequence as f32 * 1.7 + i as f32).sin() * 30.0,
+                    },
+                    sequence: sequence as u32 * 2 + 1,
+                    client_tick: tick,
+                },
+            ));
+            script.push((
+                tick + i as u64 + 3,
+                Input::PlayerMelee {
+                    player_id: id.to_string(),
+                    sequence: sequence as u32 * 2 + 2,
+                    client_tick: tick,
+                },
+            ));
+        }
+    }
+
+    script.extend([
+        (
+            300,
+            Input::ChangeTeam {
+                id: "cy".to_string(),
+                team: Team::Blue,
+            },
+        ),
+        (
+            450,
+            Input::PlayerDisconnected {
+                id: "di".to_string(),
+            },
+        ),
+        (
+            620,
+            Input::PlayerReconnected {
+                id: "di".to_string(),
+            },
+        ),
+        (
+            700,
+            Input::RemovePlayer {
+                id: "hal".to_string(),
+            },
+        ),
+    ]);
+    script
+}
+
+// World hash after every tick
+fn play(script: &[(u64, Input)]) -> Vec<u64> {
+    let mut hashes = Vec::new();
+    common::play(
+        &mut Game::new(MapConfig::default()),
+        script,
+        TICKS,
+        |game| hashes.push(game.world_hash()),
+    );
+    hashes
+}
+
+fn assert_same_run(first: &[u64], second: &[u64]) {
+    if let Some(tick) = first.iter().zip(second).position(|(a, b)| a != b) {
+        panic!("runs diverged after tick {}", tick + 1);
+    }
+}
+
+#[test]
+fn same_inputs_give_same_hashes() {
+    let script = script();
+    let first = play(&script);
+
+    // Every Game gets fresh HashMap seeds, so a few runs shake out order dependence
+    for _ in 0..4 {
+        assert_same_run(&first, &play(&script));
+    }
+}
+
+#[test]
+fn different_inputs_give_different_hashes() {
+    let mut script = script();
+    let first = play(&script);
+
+    script.push((
+        800,
+        Input::PlayerMove {
+            player_id: "ada".to_string(),
+            velocity: Velocity { dx: 0.0, dy: 1.0 },
+            sequence: 1000,
+            client_tick: 800,
+        },
+    ));
+    let second = play(&script);
+
+    assert_eq!(first[..800], second[..800]);
+    assert_ne!(first.last(), second.last());
+}
+
+#[test]
+fn timestep_ignores_clock_jitter() {
+    let mut timestep = FixedTimestep::default();
+
+    // One second in uneven slices still comes out as exactly 50 ticks
+    let slices = [3, 17, 41, 9, 20, 1, 59, 50].map(Duration::from_millis);
+    let mut ticks = 0;
+    let mut elapsed = Duration::ZERO;
+    for slice in slices.iter().cycle() {
+        if elapsed + *slice > Duration::from_secs(1) {
+            ticks += timestep.advance(Duration::from_secs(1) - elapsed);
+            break;
+        }
+        elapsed += *slice;
+        ticks += timestep.advance(*slice);
+    }
+    assert_eq!(ticks, 50);
+
+    // A long stall only catches up so far
+    assert_eq!(timestep.advance(Duration::from_secs(3)), MAX_CATCH_UP_TICKS);
+    assert_eq!(timestep.advance(Duration::ZERO), 0);
+}
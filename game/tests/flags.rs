@@ -0,0 +1,291 @@
+// Flag lifecycle: pickups, returns, captures and the countdown that sends a dropped flag home
+
+mod common;
+
+use common::{live_game, live_game_on, teleport};
+use game::{
+    FLAG_AUTO_RETURN_DURATION, Game, GameEvent, Input, MapConfig, Position, TICK_RATE, Team,
+};
+
+fn flag(game: &Game, team: Team) -> game::Flag {
+    game.make_snapshot()
+        .flags
+        .into_iter()
+        .find(|flag| flag.team == team)
+        .unwrap()
+}
+
+// Leaving is the simplest way to drop a flag at a chosen spot
+fn drop_flag_at(game: &mut Game, carrier: &str, at: Position) {
+    teleport(game, carrier, at);
+    game.step();
+    game.apply_input(Input::RemovePlayer {
+        id: carrier.to_string(),
+    })
+    .unwrap();
+}
+
+#[test]
+fn dropped_flag_counts_down_and_returns() {
+    let mut game = live_game([("ada", Team::Red), ("bo", Team::Blue)]);
+
+    // Grab the red flag and carry it off, clear of the obstacles and the red spawns
+    let home = game.map.red.flag_position;
+    teleport(&mut game, "bo", home);
+    game.step();
+    assert_eq!(flag(&game, Team::Red).item.held_by.as_deref(), Some("bo"));
+
+    let drop_at = Position { x: 30.0, y: 20.0 };
+    teleport(&mut game, "bo", drop_at);
+    game.step();
+
+    // Leaving drops the flag where the carrier stood and starts the countdown
+    game.apply_input(Input::RemovePlayer {
+        id: "bo".to_string(),
+    })
+    .unwrap();
+    let dropped = flag(&game, Team::Red);
+    assert_eq!(dropped.item.held_by, None);
+    assert_eq!(dropped.position, drop_at);
+    assert_eq!(
+        dropped.auto_return_remaining,
+        Some(FLAG_AUTO_RETURN_DURATION)
+    );
+
+    game.drain_events();
+    let countdown_ticks = (FLAG_AUTO_RETURN_DURATION / TICK_RATE).round() as u32;
+    for _ in 0..countdown_ticks - 5 {
+        game.step();
+    }
+    let counting = flag(&game, Team::Red);
+    assert_eq!(counting.position, drop_at);
+    assert!(
+        counting
+            .auto_return_remaining
+            .is_some_and(|remaining| remaining > 0.0)
+    );
+
+    for _ in 0..10 {
+        game.step();
+    }
+    let returned = flag(&game, Team::Red);
+    assert_eq!(returned.position, home);
+    assert_eq!(returned.auto_return_remaining, None);
+    assert!(game.drain_events().iter().any(|event| matches!(
+        event,
+        GameEvent::FlagReturned {
+            team: Team::Red,
+            player_id: None,
+        }
+    )));
+}
+
+#[test]
+fn captures_only_inside_own_capture_radius() {
+    let mut game = live_game([("ada", Team::Red), ("bo", Team::Blue)]);
+    let red_home = game.map.red.flag_position;
+    let radius = game.map.red.capture_radius;
+    let blue_home = game.map.blue.flag_position;
+
+    teleport(&mut game, "ada", blue_home);
+    game.step();
+    assert_eq!(flag(&game, Team::Blue).item.held_by.as_deref(), Some("ada"));
+
+    // Just outside the zone, close enough to see the base but not to score
+    let outside = Position {
+        x: red_home.x + radius * 1.5,
+        y: red_home.y,
+    };
+    teleport(&mut game, "ada", outside);
+    game.step();
+    assert_eq!(game.score[&Team::Red], 0);
+    assert_eq!(flag(&game, Team::Blue).item.held_by.as_deref(), Some("ada"));
+
+    let inside = Position {
+        x: red_home.x + radius * 0.5,
+        y: red_home.y,
+    };
+    teleport(&mut game, "ada", inside);
+    game.step();
+    assert_eq!(game.score[&Team::Red], 1);
+    assert_eq!(game.score[&Team::Blue], 0);
+    assert_eq!(flag(&game, Team::Blue).item.held_by, None);
+    assert_eq!(flag(&game, Team::Blue).position, blue_home);
+}
+
+#[test]
+fn captures_only_while_own_flag_is_home() {
+    let mut game = live_game([("ada", Team::Red), ("bo", Team::Blue), ("cy", Team::Blue)]);
+    let red_home = game.map.red.flag_position;
+    let blue_home = game.map.blue.flag_position;
+
+    // Both flags are out at the same time
+    teleport(&mut game, "ada", blue_home);
+    teleport(&mut game, "bo", red_home);
+    game.step();
+    assert_eq!(flag(&game, Team::Blue).item.held_by.as_deref(), Some("ada"));
+    assert_eq!(flag(&game, Team::Red).item.held_by.as_deref(), Some("bo"));
+
+    let drop_at = Position { x: 30.0, y: 20.0 };
+    drop_flag_at(&mut game, "bo", drop_at);
+
+    // Standing on the base with the enemy flag isn't enough while ours is missing
+    teleport(&mut game, "ada", red_home);
+    game.step();
+    assert_eq!(game.score[&Team::Red], 0);
+    assert_eq!(flag(&game, Team::Blue).item.held_by.as_deref(), Some("ada"));
+
+    // Bring ours home first, then the capture counts
+    teleport(&mut game, "ada", drop_at);
+    game.step();
+    assert_eq!(flag(&game, Team::Red).position, red_home);
+
+    teleport(&mut game, "ada", red_home);
+    game.step();
+    assert_eq!(game.score[&Team::Red], 1);
+}
+
+#[test]
+fn touching_own_dropped_flag_returns_it() {
+    let mut game = live_game([("ada", Team::Red), ("bo", Team::Blue)]);
+    let red_home = game.map.red.flag_position;
+
+    teleport(&mut game, "bo", red_home);
+    game.step();
+    let drop_at = Position { x: 30.0, y: 20.0 };
+    drop_flag_at(&mut game, "bo", drop_at);
+    assert_eq!(flag(&game, Team::Red).position, drop_at);
+    game.drain_events();
+
+    teleport(&mut game, "ada", drop_at);
+    game.step();
+
+    let returned = flag(&game, Team::Red);
+    assert_eq!(returned.position, red_home);
+    assert_eq!(returned.item.held_by, None);
+    assert_eq!(returned.auto_return_remaining, None);
+    assert!(game.drain_events().iter().any(|event| matches!(
+        event,
+        GameEvent::FlagReturned {
+            team: Team::Red,
+            player_id: Some(id),
+        } if id == "ada"
+    )));
+    assert_eq!(game.make_snapshot().players[0].stats.returns, 1);
+}
+
+#[test]
+fn touching_dropped_enemy_flag_away_from_base_does_not_score() {
+    let mut game = live_game([("ada", Team::Red), ("bo", Team::Blue), ("cy", Team::Blue)]);
+    let red_home = game.map.red.flag_position;
+
+    teleport(&mut game, "bo", red_home);
+    game.step();
+    let drop_at = Position { x: 30.0, y: 20.0 };
+    drop_flag_at(&mut game, "bo", drop_at);
+    game.drain_events();
+
+    // Picking it back up is all that happens, nowhere near the blue base
+    teleport(&mut game, "cy", drop_at);
+    game.step();
+    assert_eq!(game.score[&Team::Blue], 0);
+    assert_eq!(flag(&game, Team::Red).item.held_by.as_deref(), Some("cy"));
+    assert!(
+        !game
+            .drain_events()
+            .iter()
+            .any(|event| matches!(event, GameEvent::FlagCaptured { .. }))
+    );
+}
+
+#[test]
+fn only_one_player_holds_a_flag() {
+    let mut game = live_game([("ada", Team::Red), ("bo", Team::Blue), ("cy", Team::Blue)]);
+    let red_home = game.map.red.flag_position;
+
+    // Both reach the flag on the same tick, one of them gets it
+    teleport(&mut game, "bo", red_home);
+    teleport(&mut game, "cy", red_home);
+    game.step();
+    let holder = flag(&game, Team::Red).item.held_by.unwrap();
+
+    // Standing on the carrier doesn't steal it
+    for _ in 0..10 {
+        game.step();
+        assert_eq!(flag(&game, Team::Red).item.held_by.as_ref(), Some(&holder));
+    }
+    let pickups = game
+        .drain_events()
+        .into_iter()
+        .filter(|event| matches!(event, GameEvent::FlagPickedUp { .. }))
+        .count();
+    assert_eq!(pickups, 1);
+}
+
+#[test]
+fn disconnecting_carrier_drops_the_flag() {
+    let mut game = live_game([("ada", Team::Red), ("bo", Team::Blue)]);
+    let red_home = game.map.red.flag_position;
+
+    teleport(&mut game, "bo", red_home);
+    game.step();
+    let drop_at = Position { x: 30.0, y: 20.0 };
+    teleport(&mut game, "bo", drop_at);
+    game.step();
+    game.drain_events();
+
+    game.apply_input(Input::PlayerDisconnected {
+        id: "bo".to_string(),
+    })
+    .unwrap();
+    let dropped = flag(&game, Team::Red);
+    assert_eq!(dropped.item.held_by, None);
+    assert_eq!(dropped.position, drop_at);
+    assert_eq!(
+        dropped.auto_return_remaining,
+        Some(FLAG_AUTO_RETURN_DURATION)
+    );
+    assert!(game.drain_events().iter().any(|event| matches!(
+        event,
+        GameEvent::FlagDropped {
+            team: Team::Red,
+            player_id: id,
+        } if id == "bo"
+    )));
+
+    // Frozen on top of the flag, they don't pick it back up
+    game.step();
+    assert_eq!(flag(&game, Team::Red).item.held_by, None);
+
+    // Back again, they have to go and get it like anyone else
+    game.apply_input(Input::PlayerReconnected {
+        id: "bo".to_string(),
+    })
+    .unwrap();
+    game.step();
+    assert_eq!(flag(&game, Team::Red).item.held_by.as_deref(), Some("bo"));
+}
+
+#[test]
+fn flag_return_time_comes_from_the_map() {
+    let mut map = MapConfig::default();
+    map.red.flag_return_seconds = 2.0;
+    let mut game = live_game_on(map, [("ada", Team::Red), ("bo", Team::Blue)]);
+
+    let home = game.map.red.flag_position;
+    teleport(&mut game, "bo", home);
+    game.step();
+    let drop_at = Position { x: 30.0, y: 20.0 };
+    drop_flag_at(&mut game, "bo", drop_at);
+    assert_eq!(flag(&game, Team::Red).auto_return_remaining, Some(2.0));
+
+    let countdown_ticks = (2.0 / TICK_RATE).round() as u32;
+    for _ in 0..countdown_ticks - 5 {
+        game.step();
+    }
+    assert_eq!(flag(&game, Team::Red).position, drop_at);
+    for _ in 0..10 {
+        game.step();
+    }
+    assert_eq!(flag(&game, Team::Red).position, home);
+}
@@ -0,0 +1,189 @@
+// Client velocities are clamped to the team's speed limit, garbage is rejected outright
+
+use game::{
+    FLAG_AUTO_RETURN_DURATION, Game, GameError, Input, MapConfig, MapError, Team, Velocity,
+};
+use serde_json::{Value, json};
+
+const DEFAULT_MAP: &str = include_str!("../maps/default.json");
+
+fn length(velocity: Velocity) -> f32 {
+    (velocity.dx * velocity.dx + velocity.dy * velocity.dy).sqrt()
+}
+
+// Scaled so the larger component is 1, which can't overflow like the length can
+fn direction(velocity: Velocity) -> Velocity {
+    let largest = velocity.dx.abs().max(velocity.dy.abs());
+    Velocity {
+        dx: velocity.dx / largest,
+        dy: velocity.dy / largest,
+    }
+}
+
+fn map_with_speeds(red: Value, blue: Value) -> Result<MapConfig, MapError> {
+    let mut map: Value = serde_json::from_str(DEFAULT_MAP).unwrap();
+    map["red"]["max_speed"] = red;
+    map["blue"]["max_speed"] = blue;
+    MapConfig::from_json(&map.to_string())
+}
+
+#[test]
+fn rejects_non_finite_components() {
+    for (dx, dy) in [
+        (f32::NAN, 0.0),
+        (0.0, f32::NAN),
+        (f32::INFINITY, 0.0),
+        (0.0, f32::NEG_INFINITY),
+        (f32::INFINITY, f32::NEG_INFINITY),
+    ] {
+        let result = Velocity { dx, dy }.clamped(40.0);
+        assert!(
+            matches!(result, Err(GameError::InvalidVelocity(_))),
+            "({}, {}) was accepted",
+            dx,
+            dy
+        );
+    }
+}
+
+#[test]
+fn scales_fast_velocities_down_keeping_direction() {
+    for velocity in [
+        Velocity {
+            dx: 300.0,
+            dy: 400.0,
+        },
+        Velocity { dx: -50.0, dy: 0.0 },
+        Velocity {
+            dx: 1e30,
+            dy: -1e30,
+        },
+        Velocity {
+            dx: f32::MAX,
+            dy: f32::MAX,
+        },
+    ] {
+        let clamped = velocity.clamped(40.0).unwrap();
+
+        assert!((length(clamped) - 40.0).abs() < 1e-3, "{:?}", clamped);
+        let (before, after) = (direction(velocity), direction(clamped));
+        assert!(
+            (before.dx - after.dx).abs() < 1e-6 && (before.dy - after.dy).abs() < 1e-6,
+            "{:?} turned into {:?}",
+            velocity,
+            clamped
+        );
+    }
+}
+
+#[test]
+fn leaves_slow_velocities_alone() {
+    for velocity in [
+        Velocity { dx: 0.0, dy: 0.0 },
+        Velocity { dx: 3.0, dy: -4.0 },
+        Velocity { dx: 24.0, dy: 32.0 },
+    ] {
+        assert_eq!(velocity.clamped(40.0).unwrap(), velocity);
+    }
+}
+
+#[test]
+fn uses_each_teams_max_speed() {
+    let map = map_with_speeds(json!(10.0), json!(60.0)).unwrap();
+    let mut game = Game::new(map);
+
+    for (id, team) in [("ada", Team::Red), ("bo", Team::Blue)] {
+        game.apply_input(Input::CreatePlayer {
+            id: id.to_string(),
+            team,
+        })
+        .unwrap();
+        game.apply_input(Input::PlayerMove {
+            player_id: id.to_string(),
+            velocity: Velocity { dx: 0.0, dy: 500.0 },
+            sequence: 1,
+            client_tick: 0,
+        })
+        .unwrap();
+    }
+
+    let snapshot = game.make_snapshot();
+    for (id, max_speed) in [("ada", 10.0), ("bo", 60.0)] {
+        let player = snapshot
+            .players
+            .iter()
+            .find(|player| player.metadata.id == id)
+            .unwrap();
+        assert_eq!(
+            player.velocity,
+            Velocity {
+                dx: 0.0,
+                dy: max_speed
+            }
+        );
+    }
+}
+
+#[test]
+fn maps_need_a_positive_max_speed() {
+    for speed in [json!(0.0), json!(-5.0)] {
+        let result = map_with_speeds(json!(40.0), speed.clone());
+        assert!(
+            matches!(result, Err(MapError::Invalid(_))),
+            "max speed {} was accepted",
+            speed
+        );
+    }
+
+    assert!(map_with_speeds(json!(40.0), json!(55.0)).is_ok());
+}
+
+#[test]
+fn maps_need_finite_positive_bounds_and_capture_radius() {
+    // 1e39 overflows f32, so this is how an infinity gets in through JSON
+    for value in [json!(0.0), json!(-5.0), json!(1e39)] {
+        for field in ["width", "height"] {
+            let mut map: Value = serde_json::from_str(DEFAULT_MAP).unwrap();
+            map[field] = value.clone();
+            let result = MapConfig::from_json(&map.to_string());
+            assert!(
+                matches!(result, Err(MapError::Invalid(_))),
+                "{} {} was accepted",
+                field,
+                value
+            );
+        }
+
+        let mut map: Value = serde_json::from_str(DEFAULT_MAP).unwrap();
+        map["blue"]["capture_radius"] = value.clone();
+        let result = MapConfig::from_json(&map.to_string());
+        assert!(
+            matches!(result, Err(MapError::Invalid(_))),
+            "capture radius {} was accepted",
+            value
+        );
+    }
+}
+
+#[test]
+fn maps_need_a_positive_flag_return_time() {
+    for value in [json!(0.0), json!(-5.0), json!(1e39)] {
+        let mut map: Value = serde_json::from_str(DEFAULT_MAP).unwrap();
+        map["red"]["flag_return_seconds"] = value.clone();
+        let result = MapConfig::from_json(&map.to_string());
+        assert!(
+            matches!(result, Err(MapError::Invalid(_))),
+            "flag return time {} was accepted",
+            value
+        );
+    }
+
+    // Left out, the flag takes the usual time
+    let mut map: Value = serde_json::from_str(DEFAULT_MAP).unwrap();
+    map["red"]
+        .as_object_mut()
+        .unwrap()
+        .remove("flag_return_seconds");
+    let map = MapConfig::from_json(&map.to_string()).unwrap();
+    assert_eq!(map.red.flag_return_seconds, FLAG_AUTO_RETURN_DURATION);
+}
@@ -0,0 +1,143 @@
+// Plays the same input script on the native and wasm builds and checks both end on the
+// snapshot in fixtures/parity_snapshot.json, so client prediction matches the server.
+//
+//   native: cargo test -p game --test wasm_parity
+//   wasm:   wasm-pack test --node -- --features wasm --test wasm_parity  (from game/)
+//
+// After an intentional simulation change, rerun native with UPDATE_FIXTURES=1.
+
+mod common;
+
+use common::{Script, Simulation};
+use game::{Game, Input, MapConfig, MatchPhase, Snapshot, Team, Velocity};
+
+const FIXTURE: &str = include_str!("fixtures/parity_snapshot.json");
+// Warmup ends on tick 500, the rest is live play with a dash, a tag and a capture
+const TICKS: u64 = 1240;
+
+fn script() -> Script {
+    let create = |id: &str, team| Input::CreatePlayer {
+        id: id.to_string(),
+        team,
+    };
+    let walk = |id: &str, dx, dy, sequence| Input::PlayerMove {
+        player_id: id.to_string(),
+        velocity: Velocity { dx, dy },
+        sequence,
+        client_tick: 0,
+    };
+    let melee = |id: &str, sequence| Input::PlayerMelee {
+        player_id: id.to_string(),
+        sequence,
+        client_tick: 0,
+    };
+
+    vec![
+        (0, create("alice", Team::Red)),
+        (0, create("bob", Team::Blue)),
+        (0, walk("alice", 40.0, 25.0, 1)),
+        (0, walk("bob", -40.0, -10.0, 1)),
+        (20, create("carol", Team::Red)),
+        (25, walk("carol", 0.0, -40.0, 1)),
+        (90, walk("alice", -13.7, 38.1, 2)),
+        (150, walk("bob", 40.0, 40.0, 2)),
+        (151, walk("bob", 0.0, 0.0, 3)),
+        (200, walk("alice", 400.0, -400.0, 3)),
+        (260, walk("carol", 33.3, 0.1, 2)),
+        (310, walk("bob", -25.0, 31.0, 4)),
+        // Refused during warmup
+        (400, melee("alice", 4)),
+        // Live, everyone is back on a spawn point. Alice dashes into bob in the bottom lane.
+        (500, walk("alice", 40.0, 0.0, 5)),
+        (500, walk("bob", -40.0, 0.0, 5)),
+        (600, melee("alice", 6)),
+        // Bob respawns in the top lane and takes the red flag home
+        (620, walk("bob", -40.0, 0.0, 6)),
+        (851, walk("bob", 0.0, -40.0, 7)),
+        (900, walk("bob", 0.0, 40.0, 8)),
+        (950, walk("bob", 40.0, 0.0, 9)),
+        (1175, walk("bob", 0.0, -40.0, 10)),
+        (1225, walk("bob", 0.0, 0.0, 11)),
+    ]
+}
+
+fn play(simulation: &mut impl Simulation) -> Snapshot {
+    common::play(simulation, &script(), TICKS, |_| {});
+
+    // Sorted by id so the comparison doesn't depend on entity iteration order
+    let mut snapshot = simulation.snapshot();
+    snapshot
+        .players
+        .sort_by(|a, b| a.metadata.id.cmp(&b.metadata.id));
+    snapshot
+}
+
+fn expected() -> Snapshot {
+    serde_json::from_str(FIXTURE).expect("parity fixture should parse")
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn native_matches_fixture() {
+    let snapshot = play(&mut Game::new(MapConfig::default()));
+
+    if std::env::var_os("UPDATE_FIXTURES").is_some() {
+        let json = serde_json::to_string_pretty(&snapshot).unwrap();
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/parity_snapshot.json"
+        );
+        std::fs::write(path, json + "\n").unwrap();
+        return;
+    }
+
+    assert_eq!(snapshot, expected());
+}
+
+// The fixture is only worth as much as the script, make sure it still reaches live play
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn script_covers_live_play() {
+    let snapshot = play(&mut Game::new(MapConfig::default()));
+    let stats = |id: &str| {
+        snapshot
+            .players
+            .iter()
+            .find(|player| player.metadata.id == id)
+            .unwrap()
+            .stats
+    };
+
+    assert_eq!(snapshot.phase, MatchPhase::Live);
+    assert_eq!(stats("alice").tags, 1);
+    assert_eq!(stats("bob").times_tagged, 1);
+    assert_eq!(stats("bob").flag_pickups, 1);
+    assert_eq!(stats("bob").captures, 1);
+    assert_eq!(snapshot.score[&Team::Blue], 1);
+}
+
+// Goes through the same JS-facing calls the browser makes
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+impl Simulation for game::WasmGame {
+    fn apply(&mut self, input: Input) {
+        let input = serde_wasm_bindgen::to_value(&input).unwrap();
+        self.apply_input(input).unwrap();
+    }
+
+    fn advance(&mut self) {
+        self.step();
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        serde_wasm_bindgen::from_value(self.make_snapshot().unwrap()).unwrap()
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+#[wasm_bindgen_test::wasm_bindgen_test]
+fn wasm_matches_fixture() {
+    let map = serde_wasm_bindgen::to_value(&MapConfig::default()).unwrap();
+    let snapshot = play(&mut game::WasmGame::new(map).unwrap());
+
+    assert_eq!(snapshot, expected());
+}
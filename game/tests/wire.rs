@@ -0,0 +1,302 @@
+// Compact wire format: every message survives a round trip, and fixtures/wire_messages.json
+// holds the samples the client's shape tables are tested against
+
+use std::collections::HashMap;
+
+use game::{
+    Flag, GameEvent, GameMessage, Item, MapConfig, MatchPhase, Metadata, Obstacle, Player,
+    PlayerDelta, Position, ScoreboardEntry, Snapshot, SnapshotDelta, Stats, Team, TeamBase,
+    Velocity, Welcome, WireFormat, Zone,
+};
+use serde_json::{Map, Value};
+
+const FIXTURE: &str = include_str!("fixtures/wire_messages.json");
+
+// Every field gets its own value so a swapped pair of fields can't decode to the same thing
+fn position(x: f32) -> Position {
+    Position { x, y: x + 0.5 }
+}
+
+fn stats(base: u32) -> Stats {
+    Stats {
+        tags: base,
+        times_tagged: base + 1,
+        flag_pickups: base + 2,
+        captures: base + 3,
+        returns: base + 4,
+        carry_time: base as f32 + 5.5,
+    }
+}
+
+fn player(id: &str, base: u32) -> Player {
+    Player {
+        metadata: Metadata { id: id.to_string() },
+        position: position(base as f32),
+        velocity: Velocity {
+            dx: base as f32 + 1.25,
+            dy: base as f32 + 1.75,
+        },
+        team: Team::Blue,
+        melee_active: true,
+        stats: stats(base + 10),
+        connected: false,
+        last_processed_input: base + 20,
+        last_processed_tick: base as u64 + 30,
+    }
+}
+
+fn flag(team: Team, held_by: Option<&str>) -> Flag {
+    Flag {
+        position: position(30.0),
+        team,
+        item: Item {
+            held_by: held_by.map(str::to_string),
+        },
+        auto_return_remaining: held_by.is_none().then_some(7.5),
+    }
+}
+
+fn score() -> HashMap<Team, u32> {
+    HashMap::from([(Team::Red, 2), (Team::Blue, 1)])
+}
+
+fn team_base(offset: f32) -> TeamBase {
+    TeamBase {
+        flag_position: position(offset),
+        spawn_positions: vec![position(offset + 1.0), position(offset + 2.0)],
+        capture_radius: offset + 3.0,
+        max_speed: offset + 4.0,
+        flag_return_seconds: offset + 5.0,
+    }
+}
+
+fn welcome() -> GameMessage {
+    GameMessage::Welcome(Welcome {
+        protocol_version: 7,
+        server_build: "1.2.3".to_string(),
+        player_id: "ada".to_string(),
+        session_token: "token".to_string(),
+        tick_rate: 0.02,
+        map: MapConfig {
+            name: "arena".to_string(),
+            width: 200.0,
+            height: 100.0,
+            red: team_base(10.0),
+            blue: team_base(50.0),
+            zones: vec![Zone {
+                name: "middle".to_string(),
+                min: position(90.0),
+                max: position(110.0),
+            }],
+            obstacles: vec![
+                Obstacle::Rect {
+                    min: position(60.0),
+                    max: position(70.0),
+                },
+                Obstacle::Circle {
+                    center: position(80.0),
+                    radius: 4.5,
+                },
+            ],
+        },
+    })
+}
+
+fn snapshot() -> GameMessage {
+    GameMessage::Snapshot(Snapshot {
+        sequence: 42,
+        players: vec![player("ada", 1), player("bo", 100)],
+        flags: vec![flag(Team::Red, Some("bo")), flag(Team::Blue, None)],
+        score: score(),
+        phase: MatchPhase::Overtime,
+        time_remaining: 12.5,
+    })
+}
+
+fn delta() -> GameMessage {
+    GameMessage::Delta(SnapshotDelta {
+        sequence: 43,
+        baseline: 40,
+        added_players: vec![player("di", 3)],
+        changed_players: vec![
+            PlayerDelta {
+                id: "ada".to_string(),
+                position: Some(position(4.0)),
+                velocity: Some(Velocity { dx: 5.0, dy: 6.0 }),
+                team: Some(Team::Red),
+                melee_active: Some(false),
+                stats: Some(stats(7)),
+                connected: Some(true),
+                last_processed_input: Some(8),
+                last_processed_tick: Some(9),
+            },
+            PlayerDelta {
+                id: "bo".to_string(),
+                position: Some(position(9.0)),
+                velocity: None,
+                team: None,
+                melee_active: None,
+                stats: None,
+                connected: None,
+                last_processed_input: None,
+                last_processed_tick: None,
+            },
+        ],
+        removed_players: vec!["cy".to_string()],
+        flags: vec![flag(Team::Red, None)],
+        score: Some(score()),
+        phase: Some(MatchPhase::Live),
+        time_remaining: 11.5,
+    })
+}
+
+fn events() -> GameMessage {
+    let events = vec![
+        GameEvent::PlayerTagged {
+            attacker: "ada".to_string(),
+            victim: "bo".to_string(),
+        },
+        GameEvent::FlagPickedUp {
+            team: Team::Red,
+            player_id: "bo".to_string(),
+        },
+        GameEvent::FlagDropped {
+            team: Team::Red,
+            player_id: "bo".to_string(),
+        },
+        GameEvent::FlagReturned {
+            team: Team::Blue,
+            player_id: None,
+        },
+        GameEvent::FlagCaptured {
+            team: Team::Blue,
+            player_id: "ada".to_string(),
+        },
+        GameEvent::PlayerJoined {
+            id: "cy".to_string(),
+            team: Team::Blue,
+        },
+        GameEvent::PlayerLeft {
+            id: "cy".to_string(),
+        },
+        GameEvent::PlayerDisconnected {
+            id: "bo".to_string(),
+        },
+        GameEvent::PlayerReconnected {
+            id: "bo".to_string(),
+        },
+        GameEvent::PlayerChangedTeam {
+            id: "ada".to_string(),
+            team: Team::Blue,
+        },
+        GameEvent::MatchPhaseChanged {
+            phase: MatchPhase::Finished,
+        },
+        GameEvent::MatchFinished {
+            score: score(),
+            scoreboard: vec![ScoreboardEntry {
+                id: "ada".to_string(),
+                team: Team::Red,
+                stats: stats(60),
+            }],
+        },
+    ];
+
+    // A new event has to be added above before this compiles again
+    let covered = events
+        .iter()
+        .map(|event| match event {
+            GameEvent::PlayerTagged { .. } => 0,
+            GameEvent::FlagPickedUp { .. } => 1,
+            GameEvent::FlagDropped { .. } => 2,
+            GameEvent::FlagReturned { .. } => 3,
+            GameEvent::FlagCaptured { .. } => 4,
+            GameEvent::PlayerJoined { .. } => 5,
+            GameEvent::PlayerLeft { .. } => 6,
+            GameEvent::PlayerDisconnected { .. } => 7,
+            GameEvent::PlayerReconnected { .. } => 8,
+            GameEvent::PlayerChangedTeam { .. } => 9,
+            GameEvent::MatchPhaseChanged { .. } => 10,
+            GameEvent::MatchFinished { .. } => 11,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(covered, (0..12).collect::<Vec<_>>());
+
+    GameMessage::Events(events)
+}
+
+fn messages() -> Vec<(&'static str, GameMessage)> {
+    [welcome(), snapshot(), delta(), events()]
+        .into_iter()
+        .map(|message| {
+            // A new message needs a sample above before this compiles again
+            let name = match &message {
+                GameMessage::Welcome(_) => "welcome",
+                GameMessage::Snapshot(_) => "snapshot",
+                GameMessage::Delta(_) => "delta",
+                GameMessage::Events(_) => "events",
+            };
+            (name, message)
+        })
+        .collect()
+}
+
+// GameMessage has no PartialEq, the named JSON form compares everything
+fn named(message: &GameMessage) -> Value {
+    serde_json::to_value(message).unwrap()
+}
+
+#[test]
+fn compact_round_trips_every_message() {
+    for (name, message) in messages() {
+        let bytes = WireFormat::Compact.encode(&message).unwrap();
+        let decoded: GameMessage = WireFormat::Compact.decode(&bytes).unwrap();
+        assert_eq!(
+            named(&decoded),
+            named(&message),
+            "{} changed in a round trip",
+            name
+        );
+    }
+}
+
+// Each message compact, as the client's decoder receives it, and named, as it should come out.
+// client/src/lib/wire.test.ts checks the shape tables in wire.ts against this.
+fn fixture() -> Value {
+    let messages = messages()
+        .into_iter()
+        .map(|(name, message)| {
+            let bytes = WireFormat::Compact.encode(&message).unwrap();
+            let compact: Value = WireFormat::Compact.decode(&bytes).unwrap();
+            let sample = Map::from_iter([
+                ("compact".to_string(), compact),
+                ("named".to_string(), named(&message)),
+            ]);
+            (name.to_string(), Value::Object(sample))
+        })
+        .collect::<Map<_, _>>();
+
+    Value::Object(messages)
+}
+
+// After changing a message type, rerun with UPDATE_FIXTURES=1 and update wire.ts to match
+#[test]
+fn compact_fixture_is_current() {
+    let fixture = fixture();
+
+    if std::env::var_os("UPDATE_FIXTURES").is_some() {
+        let json = serde_json::to_string_pretty(&fixture).unwrap();
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/wire_messages.json"
+        );
+        std::fs::write(path, json + "\n").unwrap();
+        return;
+    }
+
+    let expected: Value = serde_json::from_str(FIXTURE).expect("wire fixture should parse");
+    assert_eq!(
+        fixture, expected,
+        "messages changed shape, rerun with UPDATE_FIXTURES=1 and check client/src/lib/wire.ts"
+    );
+}
@@ -0,0 +1,111 @@
+// CPU per tick spent encoding what each connection is sent as the client count grows.
+// Drives the same Outgoing calls as receive_game_snapshots, with most clients acking the
+// same tick and a few lagging behind, against diffing and encoding once per connection.
+// Run with `cargo bench -p server`
+
+#[path = "../src/outgoing.rs"]
+mod outgoing;
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use axum::body::Bytes;
+use game::{Game, GameMessage, Input, MapConfig, Snapshot, Team, Velocity, WireFormat};
+use outgoing::Outgoing;
+
+const TICKS: u32 = 200;
+const PLAYERS: usize = 20;
+const CLIENT_COUNTS: [usize; 5] = [1, 10, 50, 100, 500];
+// What the client sends unless told otherwise
+const FORMAT: WireFormat = WireFormat::Compact;
+// Ticks between a snapshot going out and its ack coming back
+const ACK_LAG: usize = 2;
+// Every this many clients is on a slower link and acks further back
+const SLOW_CLIENT_EVERY: usize = 10;
+const SLOW_ACK_LAG: usize = 5;
+
+// The snapshot a client acked, None until it has acked anything
+fn baseline(history: &VecDeque<Outgoing>, client: usize) -> Option<&Snapshot> {
+    let lag = if client.is_multiple_of(SLOW_CLIENT_EVERY) {
+        SLOW_ACK_LAG
+    } else {
+        ACK_LAG
+    };
+    history
+        .len()
+        .checked_sub(lag)
+        .and_then(|index| history[index].snapshot())
+}
+
+fn main() {
+    let mut game = Game::new(MapConfig::default());
+    for i in 0..PLAYERS {
+        let id = format!("player-{}", i);
+        let team = if i % 2 == 0 { Team::Red } else { Team::Blue };
+        game.apply_input(Input::CreatePlayer {
+            id: id.clone(),
+            team,
+        })
+        .unwrap();
+        game.apply_input(Input::PlayerMove {
+            player_id: id,
+            velocity: Velocity {
+                dx: 10.0,
+                dy: i as f32 - PLAYERS as f32 / 2.0,
+            },
+            sequence: 1,
+            client_tick: 0,
+        })
+        .unwrap();
+    }
+
+    println!("{} players, {} ticks per run", PLAYERS, TICKS);
+    println!("{:>8} {:>16} {:>16}", "clients", "per client", "shared");
+
+    for clients in CLIENT_COUNTS {
+        let mut per_client = Duration::ZERO;
+        let mut shared = Duration::ZERO;
+        let mut history: VecDeque<Outgoing> = VecDeque::new();
+
+        for _ in 0..TICKS {
+            game.step();
+            let outgoing = Outgoing::new(GameMessage::Snapshot(game.make_snapshot()));
+            let snapshot = outgoing.snapshot().unwrap();
+
+            // Every connection diffs and encodes against its own baseline
+            let start = Instant::now();
+            for client in 0..clients {
+                let message = match baseline(&history, client) {
+                    Some(baseline) => GameMessage::Delta(snapshot.diff(baseline)),
+                    None => GameMessage::Snapshot(snapshot.clone()),
+                };
+                let bytes: Bytes = FORMAT.encode(&message).unwrap().into();
+                std::hint::black_box(bytes);
+            }
+            per_client += start.elapsed();
+
+            // Connections that acked the same tick share one diff and encode
+            let start = Instant::now();
+            for client in 0..clients {
+                let bytes = match baseline(&history, client) {
+                    Some(baseline) => outgoing.delta_bytes(baseline, FORMAT),
+                    None => outgoing.bytes(FORMAT),
+                };
+                std::hint::black_box(bytes.unwrap());
+            }
+            shared += start.elapsed();
+
+            if history.len() == SLOW_ACK_LAG {
+                history.pop_front();
+            }
+            history.push_back(outgoing);
+        }
+
+        println!(
+            "{:>8} {:>13.1} us {:>13.1} us",
+            clients,
+            per_client.as_secs_f64() * 1e6 / TICKS as f64,
+            shared.as_secs_f64() * 1e6 / TICKS as f64,
+        );
+    }
+}
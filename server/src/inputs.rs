@@ -0,0 +1,236 @@
+// Inputs gathered between ticks and applied together at the start of the next one,
+// so what an input does no longer depends on when in the interval it arrived
+
+use std::collections::{BTreeMap, VecDeque};
+
+use game::Input;
+
+// Melee swings a player can have waiting, anything past this is dropped
+pub const MELEE_QUEUE_LIMIT: usize = 3;
+// Melee swings applied per player each tick, the rest wait for later ticks
+pub const MELEE_PER_TICK: usize = 1;
+
+#[derive(Default)]
+struct PlayerInputs {
+    // Only the newest movement matters
+    movement: Option<Input>,
+    melee: VecDeque<Input>,
+}
+
+#[derive(Default)]
+pub struct InputBuffer {
+    // Joins, leaves and the like from the server, applied in arrival order and never dropped
+    control: Vec<Input>,
+    // By id so players are applied in the same order every tick
+    players: BTreeMap<String, PlayerInputs>,
+}
+
+impl InputBuffer {
+    // Returns false if the input was over budget and dropped
+    pub fn push(&mut self, input: Input) -> bool {
+        match &input {
+            Input::PlayerMove { player_id, .. } => {
+                let player = self.players.entry(player_id.clone()).or_default();
+                player.movement = Some(input);
+            }
+            Input::PlayerMelee { player_id, .. } => {
+                let player = self.players.entry(player_id.clone()).or_default();
+                if player.melee.len() >= MELEE_QUEUE_LIMIT {
+                    return false;
+                }
+                player.melee.push_back(input);
+            }
+            // Anything still waiting was sent before the player left or froze
+            Input::RemovePlayer { id } | Input::PlayerDisconnected { id } => {
+                self.players.remove(id);
+                self.control.push(input);
+            }
+            _ => self.control.push(input),
+        }
+        true
+    }
+
+    // Everything due this tick, in the order it should be applied
+    pub fn drain(&mut self) -> Vec<Input> {
+        let mut inputs = std::mem::take(&mut self.control);
+
+        for player in self.players.values_mut() {
+            let melee = player.melee.len().min(MELEE_PER_TICK);
+            let mut due = player.melee.drain(..melee).collect::<Vec<_>>();
+
+            // A move sent after a swing that's still queued waits for it, applying it now would
+            // report its sequence as processed while the swing hasn't happened yet
+            let behind_melee = match (&player.movement, player.melee.front()) {
+                (Some(movement), Some(next)) => sequence(movement) > sequence(next),
+                _ => false,
+            };
+            if !behind_melee {
+                due.extend(player.movement.take());
+            }
+
+            // In the order the client sent them, so a swing goes the way the player was moving then
+            due.sort_by_key(sequence);
+            inputs.extend(due);
+        }
+
+        self.players
+            .retain(|_, player| player.movement.is_some() || !player.melee.is_empty());
+        inputs
+    }
+}
+
+fn sequence(input: &Input) -> u32 {
+    match input {
+        Input::PlayerMove { sequence, .. } | Input::PlayerMelee { sequence, .. } => *sequence,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game::{Team, Velocity};
+
+    use super::*;
+
+    fn walk(id: &str, sequence: u32) -> Input {
+        Input::PlayerMove {
+            player_id: id.to_string(),
+            velocity: Velocity {
+                dx: sequence as f32,
+                dy: 0.0,
+            },
+            sequence,
+            client_tick: 0,
+        }
+    }
+
+    fn melee(id: &str, sequence: u32) -> Input {
+        Input::PlayerMelee {
+            player_id: id.to_string(),
+            sequence,
+            client_tick: 0,
+        }
+    }
+
+    // Input has no PartialEq, this is enough to tell them apart
+    fn describe(inputs: Vec<Input>) -> Vec<String> {
+        inputs
+            .into_iter()
+            .map(|input| match input {
+                Input::PlayerMove {
+                    player_id,
+                    sequence,
+                    ..
+                } => format!("move {} {}", player_id, sequence),
+                Input::PlayerMelee {
+                    player_id,
+                    sequence,
+                    ..
+                } => format!("melee {} {}", player_id, sequence),
+                Input::CreatePlayer { id, .. } => format!("create {}", id),
+                Input::RemovePlayer { id } => format!("remove {}", id),
+                Input::PlayerDisconnected { id } => format!("disconnect {}", id),
+                other => format!("{:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn latest_movement_wins() {
+        let mut buffer = InputBuffer::default();
+        for sequence in 1..=3 {
+            assert!(buffer.push(walk("ada", sequence)));
+        }
+
+        assert_eq!(describe(buffer.drain()), ["move ada 3"]);
+        assert!(buffer.drain().is_empty());
+    }
+
+    #[test]
+    fn melee_is_limited_per_queue_and_per_tick() {
+        let mut buffer = InputBuffer::default();
+        for sequence in 1..=MELEE_QUEUE_LIMIT as u32 {
+            assert!(buffer.push(melee("ada", sequence)));
+        }
+        assert!(!buffer.push(melee("ada", 99)));
+
+        // One swing a tick until the queue is empty
+        for sequence in 1..=MELEE_QUEUE_LIMIT as u32 {
+            let due = describe(buffer.drain());
+            assert_eq!(due.len(), MELEE_PER_TICK);
+            assert_eq!(due, [format!("melee ada {}", sequence)]);
+        }
+        assert!(buffer.drain().is_empty());
+
+        // Room again once the queue has drained
+        assert!(buffer.push(melee("ada", 100)));
+    }
+
+    #[test]
+    fn move_waits_behind_an_earlier_queued_melee() {
+        let mut buffer = InputBuffer::default();
+        buffer.push(walk("ada", 1));
+        buffer.push(melee("ada", 2));
+        buffer.push(melee("ada", 3));
+        buffer.push(walk("ada", 4));
+
+        // The second swing is still queued, so the newer move can't go yet
+        assert_eq!(describe(buffer.drain()), ["melee ada 2"]);
+        assert_eq!(describe(buffer.drain()), ["melee ada 3", "move ada 4"]);
+        assert!(buffer.drain().is_empty());
+    }
+
+    #[test]
+    fn move_sent_before_a_melee_goes_first() {
+        let mut buffer = InputBuffer::default();
+        buffer.push(walk("ada", 1));
+        buffer.push(melee("ada", 2));
+
+        assert_eq!(describe(buffer.drain()), ["move ada 1", "melee ada 2"]);
+    }
+
+    #[test]
+    fn leaving_discards_buffered_input() {
+        let mut buffer = InputBuffer::default();
+        buffer.push(walk("ada", 1));
+        buffer.push(melee("ada", 2));
+        buffer.push(walk("bo", 1));
+        buffer.push(melee("bo", 2));
+
+        buffer.push(Input::RemovePlayer {
+            id: "ada".to_string(),
+        });
+        buffer.push(Input::PlayerDisconnected {
+            id: "bo".to_string(),
+        });
+
+        assert_eq!(describe(buffer.drain()), ["remove ada", "disconnect bo"]);
+    }
+
+    #[test]
+    fn control_inputs_go_before_players_in_id_order() {
+        let mut buffer = InputBuffer::default();
+        buffer.push(walk("cy", 1));
+        buffer.push(walk("ada", 1));
+        buffer.push(Input::CreatePlayer {
+            id: "di".to_string(),
+            team: Team::Red,
+        });
+        buffer.push(melee("bo", 1));
+        buffer.push(Input::CreatePlayer {
+            id: "ed".to_string(),
+            team: Team::Blue,
+        });
+
+        assert_eq!(
+            describe(buffer.drain()),
+            [
+                "create di",
+                "create ed",
+                "move ada 1",
+                "melee bo 1",
+                "move cy 1"
+            ]
+        );
+    }
+}
@@ -1,7 +1,17 @@
+mod inputs;
+mod metrics;
+mod outgoing;
+mod rooms;
+mod sessions;
+
+use std::collections::VecDeque;
 use std::sync::Arc;
 use std::time::Duration;
 
+use axum::Json;
 use axum::extract::State;
+use axum::extract::ws::{CloseFrame, close_code};
+use axum::http::header;
 use axum::response::IntoResponse;
 use axum::{
     Router,
@@ -11,16 +21,30 @@ use axum::{
 };
 use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
-use game::{Game, Input, Snapshot, Team};
+use game::{
+    CLOSE_BAD_HANDSHAKE, CLOSE_ID_IN_USE, CLOSE_PROTOCOL_MISMATCH, CLOSE_ROOM_FULL,
+    CLOSE_SESSION_REPLACED, ClientInput, ClientMessage, FixedTimestep, Game, GameMessage, Input,
+    MapConfig, PROTOCOL_VERSION, TICK_MILLIS, TICK_RATE, Team, Welcome, WireFormat,
+};
+use inputs::InputBuffer;
+use metrics::Metrics;
+use outgoing::Outgoing;
+use rooms::{RoomConnection, RoomManager};
 use serde::Deserialize;
-use tokio::sync::{broadcast, mpsc};
+use sessions::{DEFAULT_RECONNECT_GRACE, SessionHandle, SessionManager};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::time::{Instant, MissedTickBehavior};
 
-const TICK_RATE: f32 = 0.02;
+// How many sent snapshots we keep around as possible delta baselines
+const SNAPSHOT_HISTORY: usize = 32;
+// How long a new connection has to say hello
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
 
-#[derive(Debug)]
 pub struct ServerState {
-    pub input_tx: mpsc::UnboundedSender<Input>,
-    pub snapshot_rx: broadcast::Receiver<Snapshot>,
+    pub rooms: RoomManager,
+    pub sessions: SessionManager,
+    pub metrics: Arc<Metrics>,
 }
 
 pub type SharedServerState = Arc<ServerState>;
@@ -28,35 +52,308 @@ pub type SharedServerState = Arc<ServerState>;
 #[derive(Deserialize)]
 struct ConnectParams {
     id: String,
-    team: Team,
+    // Preferred team, only honoured if it keeps the teams even
+    team: Option<Team>,
+    #[serde(default = "default_room")]
+    room: String,
+    #[serde(default)]
+    format: WireFormat,
+    // Token from a previous welcome, resumes that player if they're still held
+    session: Option<String>,
+    // From /matchmake, claims the slot it reserved in the room
+    ticket: Option<String>,
+}
+
+fn default_room() -> String {
+    "default".to_string()
 }
 
-pub async fn handle_socket(
-    socket: WebSocket,
+pub(crate) async fn handle_socket(
+    mut socket: WebSocket,
     shared_server_state: SharedServerState,
     params: ConnectParams,
 ) {
-    println!("Client ID: {}", params.id);
+    println!(
+        "Client ID: {} (room {}, {:?} format)",
+        params.id, params.room, params.format
+    );
+
+    if let Err(frame) = handshake(&mut socket, params.format).await {
+        println!("Rejecting {}: {}", params.id, frame.reason);
+        let _ = socket.send(Message::Close(Some(frame))).await;
+        return;
+    }
+
+    // Settle who this connection plays as before splitting, a rejection still needs the socket
+    let (room_id, connection, session) = match join_room(&shared_server_state, &params) {
+        Ok(joined) => joined,
+        Err(frame) => {
+            println!("Rejecting {}: {}", params.id, frame.reason);
+            let _ = socket.send(Message::Close(Some(frame))).await;
+            return;
+        }
+    };
 
     let (ws_sender, ws_receiver) = socket.split();
 
-    // TODO: Allow for multiple concurrent games | here should send message to game manager
+    let player_id = params.id.clone();
+    // Latest snapshot sequence the client has acked
+    let (ack_tx, ack_rx) = watch::channel(None);
+
+    let welcome = Welcome {
+        protocol_version: PROTOCOL_VERSION,
+        server_build: env!("CARGO_PKG_VERSION").to_string(),
+        player_id: player_id.clone(),
+        session_token: session.token.clone(),
+        tick_rate: TICK_RATE,
+        map: shared_server_state.rooms.map().clone(),
+    };
 
     tokio::spawn(receive_game_snapshots(
         ws_sender,
-        shared_server_state.snapshot_rx.resubscribe(),
+        connection.snapshot_rx,
+        ack_rx,
+        session.kicked.clone(),
+        welcome,
+        params.format,
+        shared_server_state.metrics.clone(),
     ));
 
-    forward_player_inputs(params, ws_receiver, shared_server_state.input_tx.clone()).await;
+    forward_player_inputs(
+        &params,
+        ws_receiver,
+        &connection.input_tx,
+        ack_tx,
+        session.kicked.clone(),
+        &shared_server_state.metrics,
+    )
+    .await;
+
+    // Freeze the player rather than removing them, they may be back shortly
+    let freeze = || {
+        let _ = connection.input_tx.send(Input::PlayerDisconnected {
+            id: player_id.clone(),
+        });
+    };
+
+    // Another connection owns the player now, leave it alone
+    if !shared_server_state
+        .sessions
+        .disconnect(&session.token, session.connection, freeze)
+    {
+        println!("{} was taken over by another connection", player_id);
+        return;
+    }
+
+    tokio::spawn(expire_session(
+        shared_server_state.clone(),
+        session.token,
+        session.connection,
+        connection.input_tx,
+    ));
+    println!("Holding {} in {} for a reconnect", player_id, room_id);
+}
+
+// Pick up the held player if the token is still good, otherwise join fresh.
+// Returns the close frame to send if the id belongs to someone else or the room is full.
+fn join_room(
+    shared_server_state: &ServerState,
+    params: &ConnectParams,
+) -> Result<(String, RoomConnection, SessionHandle), CloseFrame> {
+    if let Some(token) = params.session.as_deref()
+        && let Some((room_id, session)) = shared_server_state.sessions.resume(token, &params.id)
+    {
+        match shared_server_state.rooms.rejoin(&room_id, &params.id) {
+            Some(connection) => {
+                println!("Resuming session for {}", params.id);
+                let reconnect = Input::PlayerReconnected {
+                    id: params.id.clone(),
+                };
+                let _ = connection.input_tx.send(reconnect);
+                return Ok((room_id, connection, session));
+            }
+            // The player is gone from the room, the session is no use anymore
+            None => shared_server_state.sessions.end(token),
+        }
+    }
+
+    let room_id = params.room.clone();
+    let Some(session) = shared_server_state.sessions.start(&params.id, &room_id) else {
+        return Err(close_frame(CLOSE_ID_IN_USE, "player id is in use"));
+    };
+    let ticket = params.ticket.as_deref();
+    let Some(connection) =
+        shared_server_state
+            .rooms
+            .join(&room_id, &params.id, params.team, ticket)
+    else {
+        shared_server_state.sessions.end(&session.token);
+        return Err(close_frame(CLOSE_ROOM_FULL, "room is full"));
+    };
+
+    let create = Input::CreatePlayer {
+        team: connection.team,
+        id: params.id.clone(),
+    };
+    if connection.input_tx.send(create).is_err() {
+        shared_server_state.sessions.end(&session.token);
+        shared_server_state.rooms.leave(&room_id, &params.id);
+        return Err(close_frame(close_code::ERROR, "room closed before joining"));
+    }
+    Ok((room_id, connection, session))
+}
+
+// Removes a dropped player once the grace period passes without them coming back
+async fn expire_session(
+    shared_server_state: SharedServerState,
+    token: String,
+    connection: u64,
+    input_tx: mpsc::UnboundedSender<Input>,
+) {
+    tokio::time::sleep(shared_server_state.sessions.grace()).await;
+
+    shared_server_state
+        .sessions
+        .expire(&token, connection, |player_id, room_id| {
+            println!("Session for {} expired", player_id);
+            // Inform game to remove player, unless the room is already gone
+            let _ = input_tx.send(Input::RemovePlayer {
+                id: player_id.to_string(),
+            });
+            shared_server_state.rooms.leave(room_id, player_id);
+        });
+}
+
+// Waits for the client's hello, returning the close frame to send if we can't talk to it
+async fn handshake(socket: &mut WebSocket, format: WireFormat) -> Result<(), CloseFrame> {
+    let bytes = match tokio::time::timeout(HANDSHAKE_TIMEOUT, socket.recv()).await {
+        Ok(Some(Ok(Message::Binary(bytes)))) => bytes,
+        Ok(_) => return Err(close_frame(CLOSE_BAD_HANDSHAKE, "expected hello")),
+        Err(_) => {
+            return Err(close_frame(
+                CLOSE_BAD_HANDSHAKE,
+                "timed out waiting for hello",
+            ));
+        }
+    };
+
+    match format.decode::<ClientMessage>(&bytes) {
+        Ok(ClientMessage::Hello { protocol_version }) if protocol_version == PROTOCOL_VERSION => {
+            Ok(())
+        }
+        Ok(ClientMessage::Hello { protocol_version }) => Err(close_frame(
+            CLOSE_PROTOCOL_MISMATCH,
+            &format!(
+                "client protocol version {} is not supported, server speaks {}",
+                protocol_version, PROTOCOL_VERSION
+            ),
+        )),
+        _ => Err(close_frame(CLOSE_BAD_HANDSHAKE, "expected hello")),
+    }
+}
+
+fn close_frame(code: u16, reason: &str) -> CloseFrame {
+    CloseFrame {
+        code,
+        reason: reason.into(),
+    }
 }
 
 async fn receive_game_snapshots(
     mut ws_sender: SplitSink<WebSocket, Message>,
-    mut snapshot_rx: broadcast::Receiver<Snapshot>,
+    mut snapshot_rx: broadcast::Receiver<Outgoing>,
+    ack_rx: watch::Receiver<Option<u64>>,
+    mut kicked: watch::Receiver<bool>,
+    welcome: Welcome,
+    format: WireFormat,
+    metrics: Arc<Metrics>,
 ) {
-    while let Ok(snapshot) = snapshot_rx.recv().await {
-        let serialized_bytes = rmp_serde::to_vec_named(&snapshot).unwrap();
-        let axum_bytes: axum::body::Bytes = serialized_bytes.into();
+    // Send the welcome once so the client knows what to render
+    let serialized_bytes = match format.encode(&GameMessage::Welcome(welcome)) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            println!("Failed to encode welcome: {}", e);
+            Metrics::increment(&metrics.encode_errors);
+            return;
+        }
+    };
+    if let Err(e) = ws_sender
+        .send(Message::Binary(serialized_bytes.into()))
+        .await
+    {
+        println!("Failed to send welcome: {}", e);
+        return;
+    }
+
+    // Snapshots sent to this client, oldest first, sharing the room's copy
+    let mut history: VecDeque<Outgoing> = VecDeque::with_capacity(SNAPSHOT_HISTORY);
+
+    loop {
+        let outgoing = tokio::select! {
+            outgoing = snapshot_rx.recv() => match outgoing {
+                Ok(outgoing) => outgoing,
+                // Missed snapshots don't matter, the next one diffs against the last ack
+                // or goes out in full if that's too old
+                Err(RecvError::Lagged(skipped)) => {
+                    println!("Connection lagged, skipped {} messages", skipped);
+                    Metrics::increment(&metrics.lagged_connections);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = kicked.changed() => {
+                let frame = close_frame(
+                    CLOSE_SESSION_REPLACED,
+                    "session taken over by another connection",
+                );
+                let _ = ws_sender.send(Message::Close(Some(frame))).await;
+                break;
+            }
+        };
+
+        // Deltas are shared by every connection that acked the same baseline
+        let axum_bytes = match outgoing.snapshot() {
+            Some(_) => {
+                let acked = *ack_rx.borrow();
+                let baseline = acked.and_then(|sequence| {
+                    history
+                        .iter()
+                        .filter_map(Outgoing::snapshot)
+                        .find(|old| old.sequence == sequence)
+                });
+                let delta = baseline.map(|baseline| outgoing.delta_bytes(baseline, format));
+                let bytes = match delta {
+                    Some(Ok(delta)) => Ok(delta),
+                    Some(Err(e)) => {
+                        println!("Failed to encode delta, sending full snapshot: {}", e);
+                        Metrics::increment(&metrics.encode_errors);
+                        outgoing.bytes(format)
+                    }
+                    // No usable baseline, send the full snapshot
+                    None => outgoing.bytes(format),
+                };
+
+                // Anything older than the ack can never be a baseline again
+                if let Some(sequence) = acked {
+                    history
+                        .retain(|old| old.snapshot().is_some_and(|old| old.sequence >= sequence));
+                }
+                if history.len() == SNAPSHOT_HISTORY {
+                    history.pop_front();
+                }
+                history.push_back(outgoing.clone());
+                bytes
+            }
+            None => outgoing.bytes(format),
+        };
+        let axum_bytes = match axum_bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("Failed to encode message, skipping it: {}", e);
+                Metrics::increment(&metrics.encode_errors);
+                continue;
+            }
+        };
 
         if let Err(e) = ws_sender.send(Message::Binary(axum_bytes)).await {
             println!("Failed to send snapshot: {}", e);
@@ -66,50 +363,79 @@ async fn receive_game_snapshots(
 }
 
 async fn forward_player_inputs(
-    params: ConnectParams,
+    params: &ConnectParams,
     mut ws_receiver: SplitStream<WebSocket>,
-    input_tx: mpsc::UnboundedSender<Input>,
+    input_tx: &mpsc::UnboundedSender<Input>,
+    ack_tx: watch::Sender<Option<u64>>,
+    mut kicked: watch::Receiver<bool>,
+    metrics: &Metrics,
 ) {
-    // Send initial player assigned message
-    input_tx
-        .send(Input::CreatePlayer {
-            team: params.team,
-            id: params.id.clone(),
-        })
-        .unwrap();
-
-    while let Some(Ok(input)) = ws_receiver.next().await {
+    // Newest input sequence seen on this connection
+    let mut last_sequence = 0;
+
+    loop {
+        let input = tokio::select! {
+            input = ws_receiver.next() => match input {
+                Some(Ok(input)) => input,
+                _ => break,
+            },
+            // Another connection took over, stop acting as this player
+            _ = kicked.changed() => break,
+        };
+
         if let Message::Binary(bytes) = input {
-            // Deserialize the bytes into an Input
-            let input = match rmp_serde::from_slice::<Input>(&bytes) {
-                Ok(input) => input,
+            // Deserialize the bytes into a ClientMessage
+            let envelope = match params.format.decode::<ClientMessage>(&bytes) {
+                Ok(ClientMessage::Input(envelope)) => envelope,
+                Ok(ClientMessage::Hello { .. }) => {
+                    println!("Ignoring repeated hello from {}", params.id);
+                    continue;
+                }
+                Ok(ClientMessage::Ack { sequence }) => {
+                    // Acks can arrive out of order, only ever move forward
+                    ack_tx.send_if_modified(|acked| {
+                        if acked.is_none_or(|acked| sequence > acked) {
+                            *acked = Some(sequence);
+                            true
+                        } else {
+                            false
+                        }
+                    });
+                    continue;
+                }
+                // Includes anything clients aren't allowed to send, like CreatePlayer
                 Err(e) => {
-                    println!("Failed to deserialize input: {}", e);
+                    println!("Rejecting message from {}: {}", params.id, e);
+                    Metrics::increment(&metrics.rejected_messages);
                     continue;
                 }
             };
 
-            match input {
-                Input::PlayerMove {
-                    player_id,
+            // A newer input already went through, applying this one would undo it
+            if envelope.sequence <= last_sequence {
+                Metrics::increment(&metrics.stale_inputs);
+                continue;
+            }
+            last_sequence = envelope.sequence;
+
+            // Clients only ever act as the player this connection joined as
+            let input = match envelope.input {
+                ClientInput::Move { velocity } => Input::PlayerMove {
+                    player_id: params.id.clone(),
                     velocity,
-                } => {
-                    input_tx
-                        .send(Input::PlayerMove {
-                            player_id,
-                            velocity,
-                        })
-                        .unwrap();
-                }
-                Input::CreatePlayer { team, id } => {
-                    panic!("Wait this shouldn't happen")
-                }
-                Input::RemovePlayer { id } => {
-                    input_tx.send(Input::RemovePlayer { id }).unwrap();
-                }
-                Input::PlayerMelee { player_id } => {
-                    input_tx.send(Input::PlayerMelee { player_id }).unwrap();
-                }
+                    sequence: envelope.sequence,
+                    client_tick: envelope.client_tick,
+                },
+                ClientInput::Melee => Input::PlayerMelee {
+                    player_id: params.id.clone(),
+                    sequence: envelope.sequence,
+                    client_tick: envelope.client_tick,
+                },
+            };
+            // Only fails once the game loop is gone, nothing left to play
+            if input_tx.send(input).is_err() {
+                println!("Room closed, dropping input from {}", params.id);
+                break;
             }
         } else {
             println!("Received non-binary message: {:?}", input);
@@ -117,40 +443,67 @@ async fn forward_player_inputs(
     }
 
     println!("Client disconnected");
-
-    // Inform game to remove player
-    input_tx
-        .send(Input::RemovePlayer { id: params.id })
-        .unwrap();
 }
 
-async fn run_game_loop(
+pub(crate) async fn run_game_loop(
     mut input_rx: mpsc::UnboundedReceiver<Input>,
-    snapshot_tx: broadcast::Sender<Snapshot>,
+    snapshot_tx: broadcast::Sender<Outgoing>,
+    map: MapConfig,
+    metrics: Arc<Metrics>,
 ) {
-    let mut game = Game::new(); // <-- exclusive owner
-    let mut tick = tokio::time::interval(Duration::from_secs_f32(TICK_RATE));
-    let mut snapshot_count = 0;
-    let start_time = std::time::Instant::now();
+    let mut game = Game::new(map); // <-- exclusive owner
+    let mut tick = tokio::time::interval(Duration::from_millis(TICK_MILLIS));
+    // Late ticks are made up by the timestep, not by the interval firing in a burst
+    tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut timestep = FixedTimestep::default();
+    let mut last_tick = Instant::now();
+    let mut inputs = InputBuffer::default();
 
     loop {
         tokio::select! {
-            _ = tick.tick() => {
-                // Process game tick
-                game.step(TICK_RATE);
+            // Ticks come first so a flood of inputs can't hold them up
+            biased;
+
+            now = tick.tick() => {
+                let due = timestep.advance(now - last_tick);
+                last_tick = now;
+                if due == 0 {
+                    continue;
+                }
+
+                // Everything since the last tick lands together, before the first step
+                for input in inputs.drain() {
+                    if let Err(e) = game.apply_input(input) {
+                        println!("Rejected input: {}", e);
+                        Metrics::increment(&metrics.input_errors);
+                    }
+                }
+
+                // Process game ticks, one snapshot covers all of them
+                for _ in 0..due {
+                    game.step();
+                }
+
+                // Forward anything that happened this tick before the new state
+                let events = game.drain_events();
+                if !events.is_empty() {
+                    let _ = snapshot_tx.send(Outgoing::new(GameMessage::Events(events)));
+                }
 
-                snapshot_count += 1;
-                // let elapsed = start_time.elapsed().as_secs_f32();
                 let snapshot = game.make_snapshot();
-                // println!(
-                //     "Sending snapshot #{} ({:.2}s) - Players: {:?}",
-                //     snapshot_count, elapsed, snapshot.players
-                // );
-                let _ = snapshot_tx.send(snapshot); // lagging clients drop
+                // Shared by every connection, each format is encoded by whichever sends it first
+                let _ = snapshot_tx.send(Outgoing::new(GameMessage::Snapshot(snapshot))); // lagging clients drop
             }
             result = input_rx.recv() => {
-                if let Some(cmd) = result {
-                    game.apply_input(cmd).ok(); // impossible to dead-lock
+                match result {
+                    Some(input) => {
+                        // Held until the next tick boundary
+                        if !inputs.push(input) {
+                            Metrics::increment(&metrics.dropped_inputs);
+                        }
+                    }
+                    // Every connection and the room itself are gone
+                    None => break,
                 }
             }
         }
@@ -159,20 +512,36 @@ async fn run_game_loop(
 
 #[tokio::main]
 async fn main() {
-    let (snapshot_tx, snapshot_rx) = broadcast::channel(16);
-    let (input_tx, input_rx) = mpsc::unbounded_channel();
+    // Load the map from MAP_PATH or fall back to the bundled default
+    let map = match std::env::var("MAP_PATH") {
+        Ok(path) => MapConfig::load(&path).unwrap_or_else(|e| panic!("{}: {}", path, e)),
+        Err(_) => MapConfig::default(),
+    };
+    println!("Using map: {}", map.name);
 
-    let game_loop_handle = tokio::spawn(run_game_loop(input_rx, snapshot_tx));
+    // How long a dropped player is kept around, RECONNECT_GRACE_SECS or the default
+    let grace = match std::env::var("RECONNECT_GRACE_SECS") {
+        Ok(secs) => Duration::from_secs_f32(
+            secs.parse()
+                .unwrap_or_else(|e| panic!("RECONNECT_GRACE_SECS {}: {}", secs, e)),
+        ),
+        Err(_) => DEFAULT_RECONNECT_GRACE,
+    };
 
-    // Allows speaking with the game
+    // Allows speaking with the games
+    let metrics = Arc::new(Metrics::default());
     let shared_server_state = Arc::new(ServerState {
-        input_tx,
-        snapshot_rx,
+        rooms: RoomManager::new(map, metrics.clone()),
+        sessions: SessionManager::new(grace),
+        metrics,
     });
 
     // build our application with a websocket route
     let app = Router::new()
         .route("/", get(|| async { "Hello, World!" }))
+        .route("/rooms", get(rooms_handler))
+        .route("/matchmake", get(matchmake_handler))
+        .route("/metrics", get(metrics_handler))
         .route("/ws", get(ws_handler))
         .with_state(shared_server_state);
 
@@ -198,3 +567,21 @@ async fn ws_handler(
         async move { handle_socket(socket, server_state, params).await }
     })
 }
+
+async fn rooms_handler(State(server_state): State<SharedServerState>) -> impl IntoResponse {
+    Json(server_state.rooms.list())
+}
+
+// Tells the client which room to connect to and the ticket for its slot there,
+// teams are assigned on connect
+async fn matchmake_handler(State(server_state): State<SharedServerState>) -> impl IntoResponse {
+    // Fetched by the client page, which is served from a different origin
+    (
+        [(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")],
+        Json(server_state.rooms.matchmake()),
+    )
+}
+
+async fn metrics_handler(State(server_state): State<SharedServerState>) -> impl IntoResponse {
+    Json(&*server_state.metrics).into_response()
+}
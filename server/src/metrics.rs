@@ -0,0 +1,27 @@
+// Counters for things that went wrong without taking a room down, served on /metrics
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+#[derive(Default, Serialize)]
+pub struct Metrics {
+    // Client messages that failed to decode
+    pub rejected_messages: AtomicU64,
+    // Inputs that arrived after a newer one from the same connection and were dropped
+    pub stale_inputs: AtomicU64,
+    // Melee inputs dropped because the player already had too many waiting
+    pub dropped_inputs: AtomicU64,
+    // Inputs the game refused, e.g. for a player that already left
+    pub input_errors: AtomicU64,
+    // Outgoing messages that failed to encode and were skipped
+    pub encode_errors: AtomicU64,
+    // Times a connection fell so far behind its room that messages were skipped
+    pub lagged_connections: AtomicU64,
+}
+
+impl Metrics {
+    pub fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,72 @@
+// Messages built once by the game loop and shared by every connection in the room
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use axum::body::Bytes;
+use game::{GameMessage, Snapshot, WireFormat};
+
+#[derive(Clone)]
+pub struct Outgoing {
+    message: Arc<GameMessage>,
+    // Encoded on first use per format, cloning only bumps a refcount
+    named: Arc<OnceLock<Bytes>>,
+    compact: Arc<OnceLock<Bytes>>,
+    // Deltas against each baseline a connection asked for, most clients acked the same tick
+    deltas: Arc<Mutex<HashMap<(u64, WireFormat), Bytes>>>,
+}
+
+impl Outgoing {
+    pub fn new(message: GameMessage) -> Self {
+        Outgoing {
+            message: Arc::new(message),
+            named: Arc::default(),
+            compact: Arc::default(),
+            deltas: Arc::default(),
+        }
+    }
+
+    // The snapshot inside the shared message, connections diff against it without copying
+    pub fn snapshot(&self) -> Option<&Snapshot> {
+        match &*self.message {
+            GameMessage::Snapshot(snapshot) => Some(snapshot),
+            _ => None,
+        }
+    }
+
+    pub fn bytes(&self, format: WireFormat) -> Result<Bytes, rmp_serde::encode::Error> {
+        let cell = match format {
+            WireFormat::Named => &self.named,
+            WireFormat::Compact => &self.compact,
+        };
+        if let Some(bytes) = cell.get() {
+            return Ok(bytes.clone());
+        }
+
+        let bytes = format.encode(&*self.message)?.into();
+        Ok(cell.get_or_init(|| bytes).clone())
+    }
+
+    // The snapshot as a delta against the baseline, diffed and encoded by whichever connection
+    // asks first. Falls back to the full message if this isn't a snapshot.
+    pub fn delta_bytes(
+        &self,
+        baseline: &Snapshot,
+        format: WireFormat,
+    ) -> Result<Bytes, rmp_serde::encode::Error> {
+        let Some(snapshot) = self.snapshot() else {
+            return self.bytes(format);
+        };
+
+        // Held while encoding so connections waking together don't all do the same work
+        let mut deltas = self.deltas.lock().unwrap();
+        if let Some(bytes) = deltas.get(&(baseline.sequence, format)) {
+            return Ok(bytes.clone());
+        }
+
+        let delta = GameMessage::Delta(snapshot.diff(baseline));
+        let bytes: Bytes = format.encode(&delta)?.into();
+        deltas.insert((baseline.sequence, format), bytes.clone());
+        Ok(bytes)
+    }
+}
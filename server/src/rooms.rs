@@ -0,0 +1,406 @@
+// Keeps track of the running games, one game loop task per room
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use game::{Input, MapConfig, Team};
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::metrics::Metrics;
+use crate::outgoing::Outgoing;
+use crate::run_game_loop;
+
+// Matchmaking fills rooms up to this many players before opening a new one
+pub const ROOM_SIZE: usize = 10;
+// How long a slot handed out by matchmaking is held for the player to connect
+pub const RESERVATION_TIMEOUT: Duration = Duration::from_secs(10);
+// Messages a connection can fall behind before it skips ahead, each tick sends up to two
+// (events and the snapshot) so this is a bit over a second at 50 ticks per second
+const OUTGOING_BUFFER: usize = 128;
+
+struct Room {
+    input_tx: mpsc::UnboundedSender<Input>,
+    snapshot_rx: broadcast::Receiver<Outgoing>,
+    // Players in the order they joined, including ones waiting to reconnect
+    players: Vec<(String, Team)>,
+}
+
+impl Room {
+    fn team_size(&self, team: Team) -> usize {
+        self.players.iter().filter(|(_, t)| *t == team).count()
+    }
+
+    // Use the requested team unless that would make the teams uneven
+    fn assign_team(&self, requested: Option<Team>) -> Team {
+        let red = self.team_size(Team::Red);
+        let blue = self.team_size(Team::Blue);
+
+        match requested {
+            Some(Team::Red) if red <= blue => Team::Red,
+            Some(Team::Blue) if blue <= red => Team::Blue,
+            _ if red <= blue => Team::Red,
+            _ => Team::Blue,
+        }
+    }
+
+    // Move the newest players off the bigger team until the sides are within one
+    fn rebalance(&mut self) {
+        loop {
+            let red = self.team_size(Team::Red);
+            let blue = self.team_size(Team::Blue);
+
+            let bigger = match red.abs_diff(blue) {
+                0 | 1 => return,
+                _ if red > blue => Team::Red,
+                _ => Team::Blue,
+            };
+
+            let Some((id, team)) = self.players.iter_mut().rev().find(|(_, t)| *t == bigger) else {
+                return;
+            };
+
+            *team = bigger.opponent();
+            println!("Rebalancing {} to {:?}", id, team);
+
+            let _ = self.input_tx.send(Input::ChangeTeam {
+                id: id.clone(),
+                team: *team,
+            });
+        }
+    }
+}
+
+// Channels a connection uses to talk to its room's game loop
+pub struct RoomConnection {
+    pub input_tx: mpsc::UnboundedSender<Input>,
+    pub snapshot_rx: broadcast::Receiver<Outgoing>,
+    pub team: Team,
+}
+
+// A slot held for whoever presents the ticket
+struct Reservation {
+    ticket: String,
+    expires: Instant,
+}
+
+// Where matchmaking sent a player, the ticket goes back to the server on connect
+#[derive(Debug, Serialize)]
+pub struct Matchmade {
+    pub room: String,
+    pub ticket: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomInfo {
+    pub id: String,
+    pub players: usize,
+    pub red: usize,
+    pub blue: usize,
+}
+
+pub struct RoomManager {
+    rooms: Mutex<HashMap<String, Room>>,
+    map: MapConfig,
+    metrics: Arc<Metrics>,
+    // Used to name rooms opened by matchmaking
+    next_room: Mutex<u64>,
+    // Slots matchmaking has promised per room, locked after rooms
+    reservations: Mutex<HashMap<String, Vec<Reservation>>>,
+}
+
+impl RoomManager {
+    pub fn new(map: MapConfig, metrics: Arc<Metrics>) -> Self {
+        Self {
+            rooms: Mutex::new(HashMap::new()),
+            map,
+            metrics,
+            next_room: Mutex::new(1),
+            reservations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn map(&self) -> &MapConfig {
+        &self.map
+    }
+
+    // Join a room, starting its game loop if nobody is playing there yet.
+    // A ticket from matchmaking takes up the slot it was handed out for, anyone else only gets
+    // in if the room still has space once every open reservation is counted.
+    // Returns None if the room is full.
+    pub fn join(
+        &self,
+        room_id: &str,
+        player_id: &str,
+        requested: Option<Team>,
+        ticket: Option<&str>,
+    ) -> Option<RoomConnection> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let mut reservations = self.reservations.lock().unwrap();
+
+        let reserved = live_reservations(&mut reservations, room_id);
+        let others = rooms.get(room_id).map_or(0, |room| {
+            room.players
+                .iter()
+                .filter(|(id, _)| id != player_id)
+                .count()
+        });
+
+        let slot = ticket.and_then(|ticket| {
+            reservations
+                .get(room_id)?
+                .iter()
+                .position(|reservation| reservation.ticket == ticket)
+        });
+        match slot {
+            // The slot was already counted when the ticket was handed out
+            Some(slot) => {
+                if others + reserved > ROOM_SIZE {
+                    return None;
+                }
+                reservations.get_mut(room_id).unwrap().remove(slot);
+            }
+            None if others + reserved >= ROOM_SIZE => return None,
+            None => {}
+        }
+
+        let room = rooms.entry(room_id.to_string()).or_insert_with(|| {
+            println!("Starting room: {}", room_id);
+
+            let (snapshot_tx, snapshot_rx) = broadcast::channel(OUTGOING_BUFFER);
+            let (input_tx, input_rx) = mpsc::unbounded_channel();
+            tokio::spawn(run_game_loop(
+                input_rx,
+                snapshot_tx,
+                self.map.clone(),
+                self.metrics.clone(),
+            ));
+
+            Room {
+                input_tx,
+                snapshot_rx,
+                players: Vec::new(),
+            }
+        });
+
+        room.players.retain(|(id, _)| id != player_id);
+        let team = room.assign_team(requested);
+        room.players.push((player_id.to_string(), team));
+
+        Some(RoomConnection {
+            input_tx: room.input_tx.clone(),
+            snapshot_rx: room.snapshot_rx.resubscribe(),
+            team,
+        })
+    }
+
+    // Reattach to a player still held in a room, keeping whatever team they're on now
+    pub fn rejoin(&self, room_id: &str, player_id: &str) -> Option<RoomConnection> {
+        let rooms = self.rooms.lock().unwrap();
+        let room = rooms.get(room_id)?;
+        let (_, team) = room.players.iter().find(|(id, _)| id == player_id)?;
+
+        Some(RoomConnection {
+            input_tx: room.input_tx.clone(),
+            snapshot_rx: room.snapshot_rx.resubscribe(),
+            team: *team,
+        })
+    }
+
+    // Leave a room, tearing it down once the last player is gone
+    pub fn leave(&self, room_id: &str, player_id: &str) {
+        let mut rooms = self.rooms.lock().unwrap();
+
+        let Some(room) = rooms.get_mut(room_id) else {
+            return;
+        };
+
+        room.players.retain(|(id, _)| id != player_id);
+
+        // Dropping the room's sender lets the game loop exit once connections finish
+        if room.players.is_empty() {
+            println!("Closing empty room: {}", room_id);
+            rooms.remove(room_id);
+            return;
+        }
+
+        room.rebalance();
+    }
+
+    // Pick a room for a new player, filling the fullest open room first.
+    // Holds a slot there for the returned ticket so a burst of requests can't overfill a room.
+    pub fn matchmake(&self) -> Matchmade {
+        let rooms = self.rooms.lock().unwrap();
+        let mut reservations = self.reservations.lock().unwrap();
+
+        // Rooms promised to someone may not have started yet
+        let mut candidates = rooms.keys().cloned().collect::<Vec<_>>();
+        candidates.extend(
+            reservations
+                .keys()
+                .filter(|id| !rooms.contains_key(*id))
+                .cloned(),
+        );
+
+        let mut best: Option<(String, usize)> = None;
+        for id in candidates {
+            let players = rooms.get(&id).map_or(0, |room| room.players.len());
+            let taken = players + live_reservations(&mut reservations, &id);
+            if taken >= ROOM_SIZE {
+                continue;
+            }
+            if best
+                .as_ref()
+                .is_none_or(|(best_id, best_taken)| (taken, best_id) > (*best_taken, &id))
+            {
+                best = Some((id, taken));
+            }
+        }
+
+        let id = match best {
+            Some((id, _)) => id,
+            None => self.open_room_id(&rooms, &mut reservations),
+        };
+
+        let ticket = format!("{:032x}", rand::random::<u128>());
+        reservations
+            .entry(id.clone())
+            .or_default()
+            .push(Reservation {
+                ticket: ticket.clone(),
+                expires: Instant::now() + RESERVATION_TIMEOUT,
+            });
+        Matchmade { room: id, ticket }
+    }
+
+    // Name for a new matchmaking room that isn't running or promised to anyone yet
+    fn open_room_id(
+        &self,
+        rooms: &HashMap<String, Room>,
+        reservations: &mut HashMap<String, Vec<Reservation>>,
+    ) -> String {
+        let mut next_room = self.next_room.lock().unwrap();
+        loop {
+            let id = format!("match-{}", *next_room);
+            *next_room += 1;
+
+            if !rooms.contains_key(&id) && live_reservations(reservations, &id) == 0 {
+                return id;
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<RoomInfo> {
+        let rooms = self.rooms.lock().unwrap();
+
+        let mut list = rooms
+            .iter()
+            .map(|(id, room)| RoomInfo {
+                id: id.clone(),
+                players: room.players.len(),
+                red: room.team_size(Team::Red),
+                blue: room.team_size(Team::Blue),
+            })
+            .collect::<Vec<_>>();
+
+        list.sort_by(|a, b| a.id.cmp(&b.id));
+        list
+    }
+}
+
+// Drop a room's lapsed reservations and count the rest
+fn live_reservations(reservations: &mut HashMap<String, Vec<Reservation>>, room_id: &str) -> usize {
+    let Some(slots) = reservations.get_mut(room_id) else {
+        return 0;
+    };
+
+    let now = Instant::now();
+    slots.retain(|reservation| reservation.expires > now);
+
+    if slots.is_empty() {
+        reservations.remove(room_id);
+        return 0;
+    }
+    slots.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> RoomManager {
+        RoomManager::new(MapConfig::default(), Arc::default())
+    }
+
+    #[tokio::test]
+    async fn reserved_slots_only_go_to_their_tickets() {
+        let rooms = manager();
+        for i in 0..ROOM_SIZE - 2 {
+            assert!(
+                rooms
+                    .join("default", &format!("p{}", i), None, None)
+                    .is_some()
+            );
+        }
+
+        // The two spare slots are promised, so a direct join has nowhere to go
+        let first = rooms.matchmake();
+        let second = rooms.matchmake();
+        assert_eq!(first.room, "default");
+        assert_eq!(second.room, "default");
+        assert!(rooms.join("default", "direct", None, None).is_none());
+        assert!(
+            rooms
+                .join("default", "direct", None, Some("made-up"))
+                .is_none()
+        );
+
+        assert!(
+            rooms
+                .join("default", "first", None, Some(&first.ticket))
+                .is_some()
+        );
+        assert!(
+            rooms
+                .join("default", "second", None, Some(&second.ticket))
+                .is_some()
+        );
+        // Everyone who was promised a slot got one and the room is now full
+        assert!(rooms.join("default", "direct", None, None).is_none());
+    }
+
+    #[tokio::test]
+    async fn matchmaking_opens_a_room_once_the_fullest_is_promised() {
+        let rooms = manager();
+        let room = rooms.matchmake().room;
+        for _ in 1..ROOM_SIZE {
+            assert_eq!(rooms.matchmake().room, room);
+        }
+        assert_ne!(rooms.matchmake().room, room);
+    }
+
+    #[tokio::test]
+    async fn leaving_rebalances_the_newest_player() {
+        let rooms = manager();
+        for id in ["ada", "bo", "cy", "di"] {
+            rooms.join("default", id, None, None).unwrap();
+        }
+        let teams = |rooms: &RoomManager| {
+            let info = &rooms.list()[0];
+            (info.red, info.blue)
+        };
+        assert_eq!(teams(&rooms), (2, 2));
+
+        // One short is close enough
+        rooms.leave("default", "bo");
+        assert_eq!(teams(&rooms), (2, 1));
+        assert_eq!(rooms.rejoin("default", "cy").unwrap().team, Team::Red);
+
+        // Two short moves cy, who joined red after ada
+        rooms.leave("default", "di");
+        assert_eq!(teams(&rooms), (1, 1));
+        assert_eq!(rooms.rejoin("default", "ada").unwrap().team, Team::Red);
+        assert_eq!(rooms.rejoin("default", "cy").unwrap().team, Team::Blue);
+    }
+}
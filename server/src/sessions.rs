@@ -0,0 +1,208 @@
+// Reconnect sessions, a dropped player is held for a grace period in case they come back
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+pub const DEFAULT_RECONNECT_GRACE: Duration = Duration::from_secs(15);
+
+struct Session {
+    player_id: String,
+    room_id: String,
+    // Bumped on every resume so a stale connection or grace timer knows it lost the race
+    connection: u64,
+    connected: bool,
+    // Tells the current connection to close when another one takes over
+    kick: watch::Sender<bool>,
+}
+
+// What a connection needs to know about the session it's running
+pub struct SessionHandle {
+    pub token: String,
+    pub connection: u64,
+    pub kicked: watch::Receiver<bool>,
+}
+
+pub struct SessionManager {
+    // Keyed by token
+    sessions: Mutex<HashMap<String, Session>>,
+    grace: Duration,
+}
+
+impl SessionManager {
+    pub fn new(grace: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            grace,
+        }
+    }
+
+    pub fn grace(&self) -> Duration {
+        self.grace
+    }
+
+    // Issue a token for a fresh join, None if someone is already playing as this id
+    // or being held for a reconnect
+    pub fn start(&self, player_id: &str, room_id: &str) -> Option<SessionHandle> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions
+            .values()
+            .any(|session| session.player_id == player_id)
+        {
+            return None;
+        }
+
+        let token = format!("{:032x}", rand::random::<u128>());
+        let (kick, kicked) = watch::channel(false);
+        sessions.insert(
+            token.clone(),
+            Session {
+                player_id: player_id.to_string(),
+                room_id: room_id.to_string(),
+                connection: 0,
+                connected: true,
+                kick,
+            },
+        );
+
+        Some(SessionHandle {
+            token,
+            connection: 0,
+            kicked,
+        })
+    }
+
+    // Take over a session, kicking whichever connection still has it.
+    // Returns the room the player is in.
+    pub fn resume(&self, token: &str, player_id: &str) -> Option<(String, SessionHandle)> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get_mut(token)?;
+        if session.player_id != player_id {
+            return None;
+        }
+
+        if session.connected {
+            let _ = session.kick.send(true);
+        }
+        let (kick, kicked) = watch::channel(false);
+        session.kick = kick;
+        session.connection += 1;
+        session.connected = true;
+
+        let handle = SessionHandle {
+            token: token.to_string(),
+            connection: session.connection,
+            kicked,
+        };
+        Some((session.room_id.clone(), handle))
+    }
+
+    pub fn end(&self, token: &str) {
+        self.sessions.lock().unwrap().remove(token);
+    }
+
+    // Mark the session dropped, false if another connection has already taken it over.
+    // `freeze` runs under the lock, so a resume can't slip its reconnect in ahead of it.
+    pub fn disconnect(&self, token: &str, connection: u64, freeze: impl FnOnce()) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(token) else {
+            return false;
+        };
+        if session.connection != connection {
+            return false;
+        }
+
+        session.connected = false;
+        freeze();
+        true
+    }
+
+    // End the session if nobody resumed it since it dropped. `remove` gets the player and room
+    // and runs under the lock, so the id can't be taken again until the player is gone.
+    pub fn expire(&self, token: &str, connection: u64, remove: impl FnOnce(&str, &str)) {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get(token) else {
+            return;
+        };
+        if session.connected || session.connection != connection {
+            return;
+        }
+
+        remove(&session.player_id, &session.room_id);
+        sessions.remove(token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> SessionManager {
+        SessionManager::new(DEFAULT_RECONNECT_GRACE)
+    }
+
+    #[test]
+    fn held_ids_cannot_be_started_again() {
+        let sessions = manager();
+        let session = sessions.start("ada", "default").unwrap();
+        assert!(sessions.start("ada", "default").is_none());
+
+        // Still held while waiting for a reconnect
+        assert!(sessions.disconnect(&session.token, session.connection, || {}));
+        assert!(sessions.start("ada", "other").is_none());
+
+        sessions.end(&session.token);
+        assert!(sessions.start("ada", "default").is_some());
+    }
+
+    #[test]
+    fn resuming_kicks_the_live_connection() {
+        let sessions = manager();
+        let first = sessions.start("ada", "default").unwrap();
+
+        // The token only works for the player it was issued to
+        assert!(sessions.resume(&first.token, "bo").is_none());
+        assert!(sessions.resume("made-up", "ada").is_none());
+        assert!(!*first.kicked.borrow());
+
+        let (room, second) = sessions.resume(&first.token, "ada").unwrap();
+        assert_eq!(room, "default");
+        assert!(*first.kicked.borrow());
+        assert!(!*second.kicked.borrow());
+        assert_ne!(second.connection, first.connection);
+    }
+
+    #[test]
+    fn stale_connections_leave_the_session_alone() {
+        let sessions = manager();
+        let first = sessions.start("ada", "default").unwrap();
+        let (_, second) = sessions.resume(&first.token, "ada").unwrap();
+
+        // The kicked connection closing doesn't freeze the player
+        let mut frozen = false;
+        assert!(!sessions.disconnect(&first.token, first.connection, || frozen = true));
+        assert!(!frozen);
+
+        assert!(sessions.disconnect(&second.token, second.connection, || frozen = true));
+        assert!(frozen);
+
+        // A resume beats the grace timer that was started before it
+        let (_, third) = sessions.resume(&first.token, "ada").unwrap();
+        let mut removed = None;
+        sessions.expire(&first.token, second.connection, |player, room| {
+            removed = Some((player.to_string(), room.to_string()))
+        });
+        assert_eq!(removed, None);
+        assert!(sessions.start("ada", "default").is_none());
+
+        // Once nobody comes back the player goes and the id is free
+        assert!(sessions.disconnect(&third.token, third.connection, || {}));
+        sessions.expire(&third.token, third.connection, |player, room| {
+            removed = Some((player.to_string(), room.to_string()))
+        });
+        assert_eq!(removed, Some(("ada".to_string(), "default".to_string())));
+        assert!(sessions.start("ada", "default").is_some());
+    }
+}